@@ -0,0 +1,61 @@
+/* generated from src/ffi.rs, don't edit by hand */
+#ifndef MINIGREP_H
+#define MINIGREP_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// match without regard to case
+#define MINIGREP_IGNORE_CASE 1u
+
+// the query is a regular expression instead of plain text
+#define MINIGREP_REGEX 2u
+
+// a compiled query
+typedef struct MinigrepQuery MinigrepQuery;
+
+// one match, all offsets are bytes into the searched buffer
+typedef struct MinigrepMatch {
+    // 1-based line number
+    size_t line;
+    size_t start;
+    size_t end;
+    // the whole line the match is on, without its line break
+    size_t line_start;
+    size_t line_end;
+} MinigrepMatch;
+
+// the matches of one search
+typedef struct MinigrepMatches MinigrepMatches;
+
+// why the last call on this thread that returned NULL failed.
+// the string belongs to the library and stays valid until the next failing call
+const char* minigrep_last_error(void);
+
+// compile `pattern` with any of the `MINIGREP_*` flags, NULL when it's invalid.
+MinigrepQuery* minigrep_query_new(const char* pattern, uint32_t flags);
+
+void minigrep_query_free(MinigrepQuery* query);
+
+// search the `len` bytes at `buf`, which must be UTF-8, line by line.
+// NULL when the text isn't UTF-8
+MinigrepMatches* minigrep_search(const MinigrepQuery* query, const uint8_t* buf, size_t len);
+
+// how many matches the search found in all
+size_t minigrep_matches_count(const MinigrepMatches* matches);
+
+// copy the next match into `out`, false once there are no more
+bool minigrep_matches_next(MinigrepMatches* matches, MinigrepMatch* out);
+
+void minigrep_matches_free(MinigrepMatches* matches);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
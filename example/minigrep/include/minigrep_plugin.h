@@ -0,0 +1,44 @@
+/* the interface between minigrep and a matcher plugin, see src/plugin.rs.
+ *
+ * a plugin is a shared library in the plugin directory
+ * (`--plugin-dir`, by default ~/.config/minigrep/plugins) exporting the two
+ * functions at the bottom of this file. `--matcher NAME` then uses the
+ * matcher called NAME instead of the usual search: every line of every file is
+ * handed to it along with the query, and the lines it accepts are printed.
+ *
+ * minigrep calls minigrep_plugin_abi_version() before anything else and skips
+ * the plugin unless it returns MINIGREP_PLUGIN_ABI_VERSION, so bump that
+ * whenever anything below changes. */
+
+#ifndef MINIGREP_PLUGIN_H
+#define MINIGREP_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define MINIGREP_PLUGIN_ABI_VERSION 1
+
+/* nonzero when `line` matches `query`. neither is NUL terminated, both are
+ * UTF-8 and the line has no line break. called from several threads at once */
+typedef int (*MinigrepMatchFn)(const char *query, size_t query_len,
+                               const char *line, size_t line_len);
+
+typedef struct MinigrepMatcherDef {
+    /* what `--matcher` selects it by */
+    const char *name;
+    const char *description;
+    MinigrepMatchFn matches;
+} MinigrepMatcherDef;
+
+typedef struct MinigrepPluginDef {
+    size_t matcher_count;
+    const MinigrepMatcherDef *matchers;
+} MinigrepPluginDef;
+
+/* return MINIGREP_PLUGIN_ABI_VERSION */
+uint32_t minigrep_plugin_abi_version(void);
+
+/* the plugin's matchers, which must stay valid until it is unloaded */
+const MinigrepPluginDef *minigrep_plugin_matchers(void);
+
+#endif
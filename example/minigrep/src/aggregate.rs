@@ -0,0 +1,177 @@
+//! `--aggregate`: count how often each distinct value matched instead of printing lines
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::regex::Regex;
+
+/// what a match is counted under
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    // the matched text itself
+    Match,
+    // the file the match is in
+    File,
+    // the text of one capture group
+    Capture(usize),
+}
+
+impl GroupBy {
+    pub fn parse(text: &str) -> Result<GroupBy, String> {
+        match text {
+            "match" => Ok(GroupBy::Match),
+            "file" => Ok(GroupBy::File),
+            n => n
+                .parse()
+                .map(GroupBy::Capture)
+                .map_err(|_| format!("can't group by '{}', use match, file or a group number", n)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    counts: HashMap<String, usize>,
+}
+
+impl Counter {
+    pub fn new() -> Counter {
+        Counter::default()
+    }
+
+    pub fn add(&mut self, key: &str) {
+        // the same idea as counting words with `entry` in 9_datastructure.rs
+        let count = self.counts.entry(key.to_string()).or_insert(0);
+        *count += 1;
+    }
+
+    /// count every match of `pattern` in `text` under its key.
+    /// matches where the chosen capture group took no part are skipped
+    pub fn add_matches(&mut self, pattern: &Regex, text: &str, group_by: GroupBy, file: &str) {
+        let mut start = 0;
+        while start <= text.len() {
+            let caps = match pattern.captures_at(text, start) {
+                Some(caps) => caps,
+                None => break,
+            };
+            let (s, e) = caps[0].unwrap();
+
+            match group_by {
+                GroupBy::Match => self.add(&text[s..e]),
+                GroupBy::File => self.add(file),
+                GroupBy::Capture(n) => {
+                    if let Some(Some((cs, ce))) = caps.get(n) {
+                        self.add(&text[*cs..*ce]);
+                    }
+                }
+            }
+
+            // step over empty matches the same way `find_iter` does
+            start = if e == s {
+                e + text[e..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                e
+            };
+        }
+    }
+
+    /// the `n` most frequent keys, ties broken alphabetically
+    pub fn top(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = self
+            .counts
+            .iter()
+            .map(|(key, count)| (key.as_str(), *count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+}
+
+/// the top-N table printed at the end of an `--aggregate` search
+pub struct Table<'a> {
+    pub counter: &'a Counter,
+    pub group_by: GroupBy,
+    pub top: usize,
+}
+
+impl<'a> fmt::Display for Table<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let heading = match self.group_by {
+            GroupBy::Match => String::from("match"),
+            GroupBy::File => String::from("file"),
+            GroupBy::Capture(n) => format!("group {}", n),
+        };
+        writeln!(f, "{:>8}  {}", "count", heading)?;
+        for (key, count) in self.counter.top(self.top) {
+            writeln!(f, "{:>8}  {}", count, key)?;
+        }
+
+        let hidden = self.counter.distinct().saturating_sub(self.top);
+        if hidden > 0 {
+            writeln!(f, "{:>8}  ({} more not shown)", "", hidden)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+GET /a 404 user_id=7
+GET /b 500 user_id=9 order_id=1
+GET /c 404 session_id=7";
+
+    #[test]
+    fn counts_matches() {
+        let pattern = Regex::new(r"\w+_id", false).unwrap();
+        let mut counter = Counter::new();
+        counter.add_matches(&pattern, LOG, GroupBy::Match, "log");
+
+        assert_eq!(
+            vec![("user_id", 2), ("order_id", 1), ("session_id", 1)],
+            counter.top(10)
+        );
+        assert_eq!(vec![("user_id", 2)], counter.top(1));
+    }
+
+    #[test]
+    fn counts_capture_groups() {
+        let pattern = Regex::new(r"GET /\w (\d+)", false).unwrap();
+        let mut counter = Counter::new();
+        counter.add_matches(&pattern, LOG, GroupBy::Capture(1), "log");
+
+        assert_eq!(vec![("404", 2), ("500", 1)], counter.top(10));
+    }
+
+    #[test]
+    fn counts_files_and_prints_table() {
+        let pattern = Regex::literal("_id", false);
+        let mut counter = Counter::new();
+        counter.add_matches(&pattern, LOG, GroupBy::File, "a.log");
+        counter.add_matches(&pattern, "x_id", GroupBy::File, "b.log");
+
+        let table = Table {
+            counter: &counter,
+            group_by: GroupBy::File,
+            top: 1,
+        };
+        assert_eq!(
+            "   count  file\n       4  a.log\n          (1 more not shown)\n",
+            table.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_group_by() {
+        assert_eq!(Ok(GroupBy::Capture(2)), GroupBy::parse("2"));
+        assert_eq!(Ok(GroupBy::File), GroupBy::parse("file"));
+        assert!(GroupBy::parse("line").is_err());
+    }
+}
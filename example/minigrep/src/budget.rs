@@ -0,0 +1,237 @@
+//! `--budget-bytes` and `--budget-matches`: warn as a search nears a limit, then stop it
+//!
+//! the `Messenger` and `LimitTracker` of 19.5_example.rs. the tracker sends each
+//! warning once instead of on every update, and tells its caller when the limit
+//! is reached so the search can be stopped. where the messages go is up to the
+//! `Messenger` handed to `run_with`, `run` prints them on stderr
+
+use std::fmt;
+
+pub trait Messenger {
+    fn send(&self, msg: &str);
+}
+
+/// the `Messenger` of the command line
+pub struct Stderr;
+
+impl Messenger for Stderr {
+    fn send(&self, msg: &str) {
+        eprintln!("minigrep: {}", msg);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Bytes,
+    Matches,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Bytes => write!(f, "bytes scanned"),
+            Limit::Matches => write!(f, "matches"),
+        }
+    }
+}
+
+pub struct LimitTracker<'a, T: Messenger + ?Sized> {
+    messenger: &'a T,
+    limit: Limit,
+    value: usize,
+    max: usize,
+    // the highest percentage already reported, so each message is sent once
+    reported: usize,
+}
+
+impl<'a, T> LimitTracker<'a, T>
+where
+    T: Messenger + ?Sized,
+{
+    pub fn new(messenger: &'a T, limit: Limit, max: usize) -> LimitTracker<'a, T> {
+        LimitTracker {
+            messenger,
+            limit,
+            value: 0,
+            max,
+            reported: 0,
+        }
+    }
+
+    /// true once the value has reached the maximum
+    pub fn set_value(&mut self, value: usize) -> bool {
+        self.value = value;
+
+        let percentage_of_max = self.value as f64 / self.max as f64;
+        // a limit of 0 is reached before anything is done
+        let (percent, msg) = if self.max == 0 || percentage_of_max >= 1.0 {
+            (100, "error: reached")
+        } else if percentage_of_max >= 0.9 {
+            (90, "urgent warning: used over 90% of")
+        } else if percentage_of_max >= 0.75 {
+            (75, "warning: used over 75% of")
+        } else {
+            (0, "")
+        };
+
+        self.report(percent, msg);
+        percent == 100
+    }
+
+    /// whether `value` would go beyond the maximum, not just reach it
+    pub fn past(&self, value: usize) -> bool {
+        value > self.max
+    }
+
+    /// stop at `value`, short of the maximum, because the next step would go
+    /// past it. the limit counts as reached with what was used so far
+    pub fn stop_at(&mut self, value: usize) {
+        self.value = value;
+        self.report(100, "error: reached");
+    }
+
+    fn report(&mut self, percent: usize, msg: &str) {
+        if percent > self.reported {
+            self.reported = percent;
+            let stop = if percent == 100 {
+                ", stopping the search"
+            } else {
+                ""
+            };
+            self.messenger.send(&format!(
+                "{} the limit of {} {} ({}){}",
+                msg, self.max, self.limit, self.value, stop
+            ));
+        }
+    }
+}
+
+/// the limits of one search, either may be left out
+pub struct Budget<'a> {
+    bytes: Option<LimitTracker<'a, dyn Messenger + 'a>>,
+    matches: Option<LimitTracker<'a, dyn Messenger + 'a>>,
+}
+
+impl<'a> Budget<'a> {
+    pub fn new(
+        messenger: &'a (dyn Messenger + 'a),
+        max_bytes: Option<u64>,
+        max_matches: Option<usize>,
+    ) -> Budget<'a> {
+        let tracker = |limit, max| LimitTracker::new(messenger, limit, max);
+        Budget {
+            bytes: max_bytes.map(|max| tracker(Limit::Bytes, max as usize)),
+            matches: max_matches.map(|max| tracker(Limit::Matches, max)),
+        }
+    }
+
+    /// true once either limit is reached
+    pub fn update(&mut self, bytes: u64, matches: usize) -> bool {
+        let over_bytes = self
+            .bytes
+            .as_mut()
+            .is_some_and(|tracker| tracker.set_value(bytes as usize));
+        let over_matches = self
+            .matches
+            .as_mut()
+            .is_some_and(|tracker| tracker.set_value(matches));
+        over_bytes || over_matches
+    }
+
+    /// whether `matches` would go beyond the limit, so the result that
+    /// brings them there shouldn't be shown
+    pub fn past_matches(&self, matches: usize) -> bool {
+        self.matches
+            .as_ref()
+            .is_some_and(|tracker| tracker.past(matches))
+    }
+
+    /// stop with `matches` shown, when the next result is past the limit
+    pub fn stop_matches(&mut self, matches: usize) {
+        if let Some(tracker) = &mut self.matches {
+            tracker.stop_at(matches);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct MockMessenger {
+        sent_messages: RefCell<Vec<String>>,
+    }
+
+    impl MockMessenger {
+        fn new() -> MockMessenger {
+            MockMessenger {
+                sent_messages: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl Messenger for MockMessenger {
+        fn send(&self, message: &str) {
+            self.sent_messages.borrow_mut().push(String::from(message));
+        }
+    }
+
+    #[test]
+    fn warns_once_per_threshold() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::new(&mock_messenger, Limit::Matches, 100);
+
+        assert!(!limit_tracker.set_value(50));
+        assert!(!limit_tracker.set_value(80));
+        assert!(!limit_tracker.set_value(85));
+        assert!(!limit_tracker.set_value(95));
+        assert!(limit_tracker.set_value(100));
+
+        let sent = mock_messenger.sent_messages.borrow();
+        assert_eq!(3, sent.len());
+        assert_eq!(
+            "warning: used over 75% of the limit of 100 matches (80)",
+            sent[0]
+        );
+        assert!(sent[2].ends_with("stopping the search"));
+    }
+
+    #[test]
+    fn either_limit_stops() {
+        let mock_messenger = MockMessenger::new();
+        let mut budget = Budget::new(&mock_messenger, Some(1000), None);
+        assert!(!budget.update(500, 1_000_000));
+        assert!(budget.update(1000, 0));
+        assert_eq!(1, mock_messenger.sent_messages.borrow().len());
+    }
+
+    #[test]
+    fn zero_and_exact_limits() {
+        let mock_messenger = MockMessenger::new();
+        let mut budget = Budget::new(&mock_messenger, None, Some(0));
+        assert!(budget.update(0, 0));
+        assert!(budget.past_matches(1));
+        assert_eq!(
+            "error: reached the limit of 0 matches (0), stopping the search",
+            mock_messenger.sent_messages.borrow()[0]
+        );
+
+        let mut budget = Budget::new(&mock_messenger, None, Some(3));
+        assert!(budget.update(0, 3));
+        // reaching the limit still shows the result, going past it doesn't
+        assert!(!budget.past_matches(3));
+        assert!(budget.past_matches(4));
+
+        // a result that would jump past the limit stops the search before it
+        let mock_messenger = MockMessenger::new();
+        let mut budget = Budget::new(&mock_messenger, None, Some(5));
+        assert!(!budget.update(0, 2));
+        assert!(budget.past_matches(7));
+        budget.stop_matches(2);
+        assert_eq!(
+            vec!["error: reached the limit of 5 matches (2), stopping the search"],
+            *mock_messenger.sent_messages.borrow()
+        );
+    }
+}
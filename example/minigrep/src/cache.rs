@@ -0,0 +1,391 @@
+//! `--cache`: remember what a search found in each file, on disk
+//!
+//! the same idea as the `Cacher` in 15_closure.rs, grown up: a result is kept
+//! for every argument instead of only the first, and it survives the process.
+//! the argument is a file searched with one query and set of options. every
+//! query and set of options gets its own file in the cache directory, holding
+//! one entry per searched file:
+//!
+//! - a file whose size and modification time are unchanged isn't read at all
+//! - a file that was touched but still hashes the same reuses its results
+//! - anything else is searched again and the entry replaced
+//!
+//! once the directory grows past its limit the least recently used files go first
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+// first line of every cache file, bumped whenever the layout changes
+const HEADER: &str = "minigrep-cache 1";
+
+/// a file's size and modification time, which change whenever it is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    pub size: u64,
+    // nanoseconds since the Unix epoch
+    pub modified: u128,
+}
+
+impl Stamp {
+    pub fn of(path: &Path) -> Option<Stamp> {
+        let meta = fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            size: meta.len(),
+            modified: modified.as_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    stamp: Stamp,
+    hash: u64,
+    results: Vec<(usize, String)>,
+}
+
+pub struct Cache {
+    key: String,
+    // where this query's entries are saved
+    file: PathBuf,
+    dir: PathBuf,
+    // bytes the whole directory may take
+    max_size: u64,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    // anything to write back
+    changed: AtomicBool,
+}
+
+impl Cache {
+    /// load the entries stored for `key`, a description of the query and its options.
+    /// a missing or damaged cache file just means starting empty
+    pub fn open(dir: &Path, key: &str, max_size: u64) -> Cache {
+        let file = dir.join(format!("{:016x}.cache", fingerprint(key.as_bytes())));
+        let entries = fs::read_to_string(&file)
+            .ok()
+            .and_then(|text| parse(&text, key))
+            .unwrap_or_default();
+
+        Cache {
+            key: key.to_string(),
+            file,
+            dir: dir.to_path_buf(),
+            max_size,
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// the results for `path` if it hasn't changed at all since they were stored
+    pub fn unchanged(&self, path: &Path, stamp: Stamp) -> Option<Vec<(usize, String)>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(path)?;
+        if entry.stamp == stamp {
+            Some(entry.results.clone())
+        } else {
+            None
+        }
+    }
+
+    /// the results for `path` if its text still hashes to the stored value,
+    /// in which case the entry takes on the new `stamp`
+    pub fn same_content(
+        &self,
+        path: &Path,
+        stamp: Stamp,
+        hash: u64,
+    ) -> Option<Vec<(usize, String)>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(path)?;
+        if entry.hash != hash {
+            return None;
+        }
+        entry.stamp = stamp;
+        self.changed.store(true, Ordering::Relaxed);
+        Some(entry.results.clone())
+    }
+
+    pub fn store(&self, path: &Path, stamp: Stamp, hash: u64, results: &[(usize, String)]) {
+        let entry = Entry {
+            stamp,
+            hash,
+            results: results.to_vec(),
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), entry);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// write the entries back, then trim the directory down to its size limit
+    pub fn save(&self) -> io::Result<()> {
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+
+        let mut text = format!("{}\n{}\n", HEADER, escape(&self.key));
+        let entries = self.entries.lock().unwrap();
+        for (path, entry) in entries.iter() {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{:016x}\t{}\n",
+                escape(&path.to_string_lossy()),
+                entry.stamp.size,
+                entry.stamp.modified,
+                entry.hash,
+                entry.results.len()
+            ));
+            for (number, line) in &entry.results {
+                text.push_str(&format!("{}\t{}\n", number, escape(line)));
+            }
+        }
+        // written next to the real file and renamed, so a reader never sees half of it
+        let partial = self.file.with_extension("tmp");
+        fs::write(&partial, text)?;
+        fs::rename(&partial, &self.file)?;
+
+        trim(&self.dir, self.max_size)
+    }
+}
+
+// the entries of a cache file, `None` when it's for another key or damaged
+fn parse(text: &str, key: &str) -> Option<HashMap<PathBuf, Entry>> {
+    let mut lines = text.lines();
+    if lines.next()? != HEADER || unescape(lines.next()?) != key {
+        return None;
+    }
+
+    let mut entries = HashMap::new();
+    while let Some(line) = lines.next() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let stamp = Stamp {
+            size: fields[1].parse().ok()?,
+            modified: fields[2].parse().ok()?,
+        };
+        let hash = u64::from_str_radix(fields[3], 16).ok()?;
+        let count: usize = fields[4].parse().ok()?;
+
+        // the count isn't trusted with an allocation, the file may be damaged
+        let mut results = Vec::new();
+        for _ in 0..count {
+            let (number, line) = lines.next()?.split_once('\t')?;
+            results.push((number.parse().ok()?, unescape(line)));
+        }
+        let entry = Entry {
+            stamp,
+            hash,
+            results,
+        };
+        entries.insert(PathBuf::from(unescape(fields[0])), entry);
+    }
+    Some(entries)
+}
+
+// delete the least recently written cache files until the rest fit in `max_size`
+fn trim(dir: &Path, max_size: u64) -> io::Result<()> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|ext| ext == "cache") {
+            let meta = entry.metadata()?;
+            files.push((meta.modified()?, meta.len(), entry.path()));
+        }
+    }
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total <= max_size {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= size;
+    }
+    Ok(())
+}
+
+/// FNV-1a, a quick hash that stays the same between runs and Rust versions
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    fingerprint_more(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+/// the `fingerprint` of text read in pieces: `hash` is that of the pieces before
+/// `bytes`, and `fingerprint(&[])` before the first one
+pub fn fingerprint_more(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// parse `64M`, `512K`, `1G` or a plain number of bytes
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+    let scale = match unit {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(format!("invalid size '{}'", text)),
+    };
+    let n = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{}'", text))?;
+    n.checked_mul(scale)
+        .ok_or_else(|| format!("size '{}' is too large", text))
+}
+
+/// where the cache goes without `--cache-dir`
+pub fn default_dir() -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir).join("minigrep"),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache").join("minigrep"),
+            None => std::env::temp_dir().join("minigrep-cache"),
+        },
+    }
+}
+
+// results can hold tabs and, with `--multiline`, newlines
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("minigrep-cache-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("round-trip");
+        let stamp = Stamp {
+            size: 42,
+            modified: 1_700_000_000_000_000_000,
+        };
+        let results = vec![(3, String::from("a\tb\\c")), (7, String::from("one\ntwo"))];
+
+        let cache = Cache::open(&dir, "query=x", 1 << 20);
+        assert_eq!(None, cache.unchanged(Path::new("src/lib.rs"), stamp));
+        cache.store(Path::new("src/lib.rs"), stamp, 99, &results);
+        cache.save().unwrap();
+
+        let cache = Cache::open(&dir, "query=x", 1 << 20);
+        assert_eq!(
+            Some(results.clone()),
+            cache.unchanged(Path::new("src/lib.rs"), stamp)
+        );
+
+        // touched but not edited
+        let touched = Stamp {
+            modified: stamp.modified + 1,
+            ..stamp
+        };
+        assert_eq!(None, cache.unchanged(Path::new("src/lib.rs"), touched));
+        assert_eq!(
+            None,
+            cache.same_content(Path::new("src/lib.rs"), touched, 100)
+        );
+        assert_eq!(
+            Some(results),
+            cache.same_content(Path::new("src/lib.rs"), touched, 99)
+        );
+
+        // another query doesn't see these entries
+        let other = Cache::open(&dir, "query=y", 1 << 20);
+        assert_eq!(None, other.unchanged(Path::new("src/lib.rs"), stamp));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn size_limit() {
+        let dir = temp_dir("limit");
+        let stamp = Stamp {
+            size: 1,
+            modified: 1,
+        };
+        let results = vec![(1, "x".repeat(1000))];
+        for key in ["a", "b", "c"] {
+            let cache = Cache::open(&dir, key, 2500);
+            cache.store(Path::new("file"), stamp, 1, &results);
+            cache.save().unwrap();
+        }
+
+        // only two of the three ~1KB files fit
+        let left = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(2, left);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_file() {
+        let dir = temp_dir("damaged");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(format!("{:016x}.cache", fingerprint(b"query=x")));
+        fs::write(
+            &file,
+            format!(
+                "{}\nquery=x\nsrc/lib.rs\t1\t1\t0\t18446744073709551615\n1\tx\n",
+                HEADER
+            ),
+        )
+        .unwrap();
+
+        let cache = Cache::open(&dir, "query=x", 1 << 20);
+        let stamp = Stamp {
+            size: 1,
+            modified: 1,
+        };
+        assert_eq!(None, cache.unchanged(Path::new("src/lib.rs"), stamp));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sizes_and_hashes() {
+        assert_eq!(Ok(64 << 20), parse_size("64M"));
+        assert_eq!(Ok(512), parse_size("512"));
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+        assert_eq!(0xcbf2_9ce4_8422_2325, fingerprint(b""));
+        assert_ne!(fingerprint(b"ab"), fingerprint(b"ba"));
+    }
+}
@@ -0,0 +1,108 @@
+//! stopping a search early, from any thread
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// shared by every worker of one search. once it fires, each worker stops at
+/// its next line and hands back whatever it has found so far
+#[derive(Debug, Default)]
+pub struct Cancel {
+    stopped: AtomicBool,
+    // set once a worker notices the deadline has passed
+    expired: AtomicBool,
+    deadline: Option<Instant>,
+}
+
+impl Cancel {
+    pub fn new() -> Cancel {
+        Cancel::default()
+    }
+
+    /// fires by itself once `timeout` has passed. a timeout too long to
+    /// have a deadline never fires
+    pub fn with_timeout(timeout: Duration) -> Cancel {
+        Cancel {
+            deadline: Instant::now().checked_add(timeout),
+            ..Cancel::default()
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        if self.stopped.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.expired.store(true, Ordering::Relaxed);
+            self.stopped.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// whether the search was stopped by its timeout, rather than finishing in time
+    pub fn timed_out(&self) -> bool {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+/// parse `1.5s`, `250ms`, `2m` or `1h`; a bare number is seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("unknown unit in duration '{}'", text)),
+    };
+
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("duration '{}' is too long", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(Ok(Duration::from_millis(250)), parse_duration("250ms"));
+        assert_eq!(Ok(Duration::from_millis(1500)), parse_duration("1.5s"));
+        assert_eq!(Ok(Duration::from_secs(120)), parse_duration("2m"));
+        assert_eq!(Ok(Duration::from_secs(3)), parse_duration("3"));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("3d").is_err());
+        assert!(parse_duration("99999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn timeout_fires() {
+        let cancel = Cancel::with_timeout(Duration::from_millis(0));
+        assert!(!cancel.timed_out());
+        assert!(cancel.is_cancelled());
+        assert!(cancel.timed_out());
+
+        let cancel = Cancel::new();
+        assert!(!cancel.is_cancelled());
+        cancel.cancel();
+        assert!(cancel.is_cancelled());
+        assert!(!cancel.timed_out());
+
+        let cancel = Cancel::with_timeout(Duration::MAX);
+        assert!(!cancel.is_cancelled());
+    }
+}
@@ -0,0 +1,265 @@
+//! `--csv` and `--tsv`: match only in chosen fields of delimited records
+//!
+//! records follow RFC 4180 with either delimiter: a field in double quotes may
+//! hold the delimiter, line breaks and `""` for a quote. the first record is the
+//! header, `--field` picks columns from it by name or by 1-based index. a record
+//! matches when the query matches any of its chosen fields, or any field at all
+//! without `--field`
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use crate::cancel::Cancel;
+use crate::regex::Regex;
+
+#[derive(Debug, PartialEq)]
+pub struct CsvError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl Error for CsvError {}
+
+#[derive(Debug, PartialEq)]
+pub struct Record<'a> {
+    // where the record starts, it may go on over several lines
+    pub line: usize,
+    // as written, without the line break at the end
+    pub text: &'a str,
+    pub fields: Vec<Cow<'a, str>>,
+}
+
+/// split `text` into records
+pub fn records(text: &str, delimiter: u8) -> Result<Vec<Record<'_>>, CsvError> {
+    let bytes = text.as_bytes();
+    let mut records = Vec::new();
+    let mut pos = 0;
+    let mut line = 1;
+
+    while pos < bytes.len() {
+        let start = pos;
+        let first_line = line;
+        let mut fields = Vec::new();
+        let end = loop {
+            if bytes[pos] == b'"' {
+                let (field, after) = quoted(text, pos + 1, &mut line).ok_or_else(|| CsvError {
+                    line: first_line,
+                    msg: String::from("quoted field is never closed"),
+                })?;
+                fields.push(field);
+                pos = after;
+            } else {
+                let field_start = pos;
+                while pos < bytes.len()
+                    && bytes[pos] != delimiter
+                    && bytes[pos] != b'\n'
+                    && !bytes[pos..].starts_with(b"\r\n")
+                {
+                    pos += 1;
+                }
+                fields.push(Cow::Borrowed(&text[field_start..pos]));
+            }
+
+            // the delimiter and line breaks are ASCII, so these are all char boundaries
+            match bytes.get(pos) {
+                Some(&b) if b == delimiter => pos += 1,
+                Some(b'\n') => {
+                    line += 1;
+                    pos += 1;
+                    break pos - 1;
+                }
+                Some(b'\r') if bytes.get(pos + 1) == Some(&b'\n') => {
+                    line += 1;
+                    pos += 2;
+                    break pos - 2;
+                }
+                None => break pos,
+                Some(_) => {
+                    return Err(CsvError {
+                        line,
+                        msg: String::from("text after a closing quote"),
+                    })
+                }
+            }
+            // a delimiter at the very end leaves one more, empty, field
+            if pos == bytes.len() {
+                fields.push(Cow::Borrowed(""));
+                break pos;
+            }
+        };
+        records.push(Record {
+            line: first_line,
+            text: &text[start..end],
+            fields,
+        });
+    }
+    Ok(records)
+}
+
+// the field of a quoted value that starts at `start`, and where the text goes on
+// after its closing quote. `None` when it isn't closed
+fn quoted<'a>(text: &'a str, start: usize, line: &mut usize) -> Option<(Cow<'a, str>, usize)> {
+    let bytes = text.as_bytes();
+    let mut pos = start;
+    let mut escaped = false;
+    loop {
+        match *bytes.get(pos)? {
+            b'"' if bytes.get(pos + 1) == Some(&b'"') => {
+                escaped = true;
+                pos += 2;
+            }
+            b'"' => break,
+            b'\n' => {
+                *line += 1;
+                pos += 1;
+            }
+            _ => pos += 1,
+        }
+    }
+    let raw = &text[start..pos];
+    let field = if escaped {
+        Cow::Owned(raw.replace("\"\"", "\""))
+    } else {
+        Cow::Borrowed(raw)
+    };
+    Some((field, pos + 1))
+}
+
+/// the 0-based columns `fields` name, each a header name or a 1-based index
+pub fn columns(header: &Record, fields: &[String]) -> Result<Vec<usize>, String> {
+    fields
+        .iter()
+        .map(|field| {
+            if let Some(i) = header.fields.iter().position(|name| name == field) {
+                return Ok(i);
+            }
+            match field.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= header.fields.len() => Ok(n - 1),
+                _ => Err(format!("no column '{}' in the header", field)),
+            }
+        })
+        .collect()
+}
+
+// the header, the records with a match in one of the chosen fields, and how
+// many matches each of those has in them
+type Matched = (Option<(usize, String)>, Vec<(usize, String)>, Vec<usize>);
+
+/// search the records of `text`, stopping after `max` matching records
+pub fn search(
+    pattern: &Regex,
+    text: &str,
+    delimiter: u8,
+    fields: &[String],
+    max: usize,
+    cancel: &Cancel,
+) -> Result<Matched, Box<dyn Error>> {
+    let records = records(text, delimiter)?;
+    let header = match records.first() {
+        Some(header) => header,
+        None => return Ok((None, Vec::new(), Vec::new())),
+    };
+    let columns = if fields.is_empty() {
+        (0..header.fields.len()).collect()
+    } else {
+        columns(header, fields)?
+    };
+
+    let mut results = Vec::new();
+    let mut counts = Vec::new();
+    for record in &records[1..] {
+        if results.len() == max || cancel.is_cancelled() {
+            break;
+        }
+        // a short record just doesn't have the later fields
+        let count: usize = columns
+            .iter()
+            .filter_map(|&i| record.fields.get(i))
+            .map(|field| pattern.find_iter(field).count())
+            .sum();
+        if count > 0 {
+            results.push((record.line, record.text.to_string()));
+            counts.push(count);
+        }
+    }
+    Ok((
+        Some((header.line, header.text.to_string())),
+        results,
+        counts,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+time,path,status
+2024-04-04T10:00:00,/index.html,200
+2024-04-04T10:00:01,\"/a, b\",404\r
+2024-04-04T10:00:02,\"/say \"\"hi\"\"
+there\",500
+";
+
+    fn fields<'a>(record: &'a Record) -> Vec<&'a str> {
+        record.fields.iter().map(|f| f.as_ref()).collect()
+    }
+
+    #[test]
+    fn quoting() {
+        let log = records(LOG, b',').unwrap();
+        assert_eq!(4, log.len());
+        assert_eq!(vec!["time", "path", "status"], fields(&log[0]));
+        assert_eq!(vec!["2024-04-04T10:00:01", "/a, b", "404"], fields(&log[2]));
+        assert_eq!(
+            vec!["2024-04-04T10:00:02", "/say \"hi\"\nthere", "500"],
+            fields(&log[3])
+        );
+        assert_eq!(4, log[3].line);
+        assert!(log[3].text.ends_with("there\",500"));
+
+        assert_eq!(vec!["a", "", ""], fields(&records("a,,", b',').unwrap()[0]));
+        assert_eq!(
+            vec!["a\"b", "c"],
+            fields(&records("a\"b\tc", b'\t').unwrap()[0])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let never_closed = records("a,b\n1,\"2\n", b',').unwrap_err();
+        assert_eq!(2, never_closed.line);
+        let after_quote = records("\"a\"b,c", b',').unwrap_err();
+        assert_eq!("text after a closing quote", after_quote.msg);
+    }
+
+    #[test]
+    fn only_chosen_fields() {
+        // "404" is in a timestamp too, but only the status counts
+        let text = "time,status\n1714040400,200\n1714040500,404\n";
+        let pattern = Regex::literal("404", false);
+        let search = |fields: &[&str]| {
+            let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+            search(&pattern, text, b',', &fields, usize::MAX, &Cancel::new())
+        };
+
+        let (header, results, counts) = search(&["status"]).unwrap();
+        assert_eq!(Some((1, String::from("time,status"))), header);
+        assert_eq!(vec![(3, String::from("1714040500,404"))], results);
+        assert_eq!(vec![1], counts);
+        // every field, timestamps included
+        assert_eq!(vec![1, 2], search(&[]).unwrap().2);
+        assert_eq!(1, search(&["2"]).unwrap().1.len());
+        assert!(search(&["code"]).is_err());
+        // past the last column is as much a typo as a wrong name
+        assert!(search(&["3"]).is_err());
+        assert!(search(&["0"]).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+//! `minigrep diff QUERY OLD_DIR NEW_DIR`: what a search finds in one tree but not the other
+//!
+//! files are paired by their path below each directory. a matching line that
+//! only moved up or down because of edits around it isn't a change, only lines
+//! that were added, removed or moved past other matches are reported:
+//!
+//! ```text
+//! -src/old.rs:12:deprecated_call()
+//! +src/new.rs:40:deprecated_call(x)
+//! >src/a.rs:7 -> src/b.rs:3:deprecated_call()
+//! ```
+//!
+//! lines are compared without their leading and trailing whitespace, so
+//! re-indenting a call doesn't count either. like grep, the exit status is 0
+//! when there is something to report and 1 when there isn't
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::cancel::Cancel;
+use crate::regex::Regex;
+use crate::stats::Stats;
+use crate::{read_contents, search_until, walk, Config};
+
+/// the matching lines of every file in a tree, by path below the tree
+pub type Found = BTreeMap<PathBuf, Vec<(usize, String)>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    Removed {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    // within one file or from one file to another
+    Moved {
+        from: (PathBuf, usize),
+        to: (PathBuf, usize),
+        text: String,
+    },
+}
+
+impl Change {
+    // where the change shows up, new side first
+    fn at(&self) -> (&Path, usize) {
+        match self {
+            Change::Added { path, line, .. } | Change::Removed { path, line, .. } => (path, *line),
+            Change::Moved { to, .. } => (&to.0, to.1),
+        }
+    }
+}
+
+pub fn run(config: Config) -> Result<Stats, Box<dyn Error>> {
+    let started = Instant::now();
+    if config.paths.len() != 2 {
+        return Err("diff needs a query, an old directory and a new directory".into());
+    }
+    let unsupported = unsupported(&config);
+    if !unsupported.is_empty() {
+        return Err(format!("diff can't be used with {}", unsupported.join(", ")).into());
+    }
+    let pattern = config.pattern()?;
+    let mut stats = Stats::default();
+    let old = search_tree(&config, &pattern, &config.paths[0], &mut stats)?;
+    let new = search_tree(&config, &pattern, &config.paths[1], &mut stats)?;
+    let changes = compare(&old, &new);
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let written = changes
+        .iter()
+        .try_for_each(|change| write_change(&mut out, change));
+
+    stats.changes = Some(changes.len());
+    stats.elapsed = started.elapsed();
+    let written = written.and_then(|_| {
+        if config.stats {
+            writeln!(out, "\n{}", stats)?;
+        }
+        out.flush()
+    });
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(stats),
+    }
+}
+
+// the options given that change what a search finds or how it's shown, which
+// diff doesn't do. those that only change how fast it goes are fine
+fn unsupported(config: &Config) -> Vec<&'static str> {
+    let options = [
+        (config.matcher.is_some(), "--matcher"),
+        (config.json_path.is_some(), "--json-path"),
+        (config.scope.is_some(), "--scope"),
+        (config.delimiter.is_some(), "--csv/--tsv"),
+        (config.window.is_some(), "--since/--until"),
+        (config.format.is_some(), "--format"),
+        (config.html.is_some(), "--html"),
+        (config.aggregate.is_some(), "--aggregate"),
+        (config.sort.is_some(), "--sort"),
+        (config.max_total.is_some(), "--max-total"),
+        (config.timeout.is_some(), "--timeout"),
+        (config.follow, "--follow"),
+        (config.git_history, "--git-history"),
+        (
+            config.budget_bytes.is_some() || config.budget_matches.is_some(),
+            "--budget-bytes/--budget-matches",
+        ),
+        (config.plan.is_some(), "--bool"),
+    ];
+    options
+        .iter()
+        .filter(|(given, _)| *given)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn write_change(out: &mut impl Write, change: &Change) -> io::Result<()> {
+    match change {
+        Change::Added { path, line, text } => {
+            writeln!(out, "+{}:{}:{}", path.display(), line, text)
+        }
+        Change::Removed { path, line, text } => {
+            writeln!(out, "-{}:{}:{}", path.display(), line, text)
+        }
+        Change::Moved { from, to, text } => writeln!(
+            out,
+            ">{}:{} -> {}:{}:{}",
+            from.0.display(),
+            from.1,
+            to.0.display(),
+            to.1,
+            text
+        ),
+    }
+}
+
+// search every file under `root`, files that can't be read are reported and skipped
+fn search_tree(
+    config: &Config,
+    pattern: &Regex,
+    root: &str,
+    stats: &mut Stats,
+) -> Result<Found, Box<dyn Error>> {
+    let mut found = Found::new();
+    let max = config.max_count.unwrap_or(usize::MAX);
+    for entry in walk::files(&[root.to_string()], &config.filter()?) {
+        let searched = entry.map_err(|e| e.to_string()).and_then(|file| {
+            let (size, contents) = read_contents(&file, config.decompress)
+                .map_err(|e| format!("{}: {}", file.display(), e))?;
+            stats.bytes_read += size;
+            let results: Vec<(usize, String)> =
+                search_until(pattern, &contents, config.multiline, max, &Cancel::new())
+                    .into_iter()
+                    .map(|(number, line)| (number, line.to_string()))
+                    .collect();
+            // a single file given as the tree is known by its name
+            let relative = match file.strip_prefix(root) {
+                Ok(relative) if relative != Path::new("") => relative.to_path_buf(),
+                _ => PathBuf::from(file.file_name().unwrap_or_default()),
+            };
+            Ok((relative, results))
+        });
+        match searched {
+            Ok((relative, results)) => {
+                stats.files_scanned += 1;
+                if !results.is_empty() {
+                    stats.files_matched += 1;
+                    stats.matched_lines += results.len();
+                    stats.matches += results
+                        .iter()
+                        .map(|(_, line)| pattern.find_iter(line).count())
+                        .sum::<usize>();
+                    found.insert(relative, results);
+                }
+            }
+            Err(e) => {
+                stats.errors += 1;
+                if !config.no_messages {
+                    eprintln!("minigrep: {}", e);
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// the changes between the matches of two trees, in path and line order
+pub fn compare(old: &Found, new: &Found) -> Vec<Change> {
+    let empty = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let mut changes = Vec::new();
+
+    let mut paths: Vec<&PathBuf> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let before = old.get(path).unwrap_or(&empty);
+        let after = new.get(path).unwrap_or(&empty);
+        let (gone, came) = unmatched(before, after);
+        let gone: Vec<Place> = gone
+            .into_iter()
+            .map(|(line, text)| (path.as_path(), line, text))
+            .collect();
+        let came: Vec<Place> = came
+            .into_iter()
+            .map(|(line, text)| (path.as_path(), line, text))
+            .collect();
+
+        // what left one place and arrived at another in the same file has moved
+        let (gone, came) = pair_moves(gone, came, &mut changes);
+        removed.extend(gone);
+        added.extend(came);
+    }
+
+    // and so has what left one file for another
+    let (removed, added) = pair_moves(removed, added, &mut changes);
+    for (path, line, text) in removed {
+        changes.push(Change::Removed {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+        });
+    }
+    for (path, line, text) in added {
+        changes.push(Change::Added {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+        });
+    }
+
+    changes.sort_by(|a, b| a.at().cmp(&b.at()));
+    changes
+}
+
+// a line number and its text
+type Line<'a> = (usize, &'a str);
+
+// the lines of `before` and `after` outside a longest common subsequence of their texts,
+// the rest are the same lines at most shifted
+fn unmatched<'a>(
+    before: &'a [(usize, String)],
+    after: &'a [(usize, String)],
+) -> (Vec<Line<'a>>, Vec<Line<'a>>) {
+    let a: Vec<&str> = before.iter().map(|(_, text)| text.trim()).collect();
+    let b: Vec<&str> = after.iter().map(|(_, text)| text.trim()).collect();
+    let mut kept = (vec![false; a.len()], vec![false; b.len()]);
+    common(&a, &b, &mut kept.0, &mut kept.1);
+
+    let left = |lines: &'a [(usize, String)], kept: &[bool]| {
+        lines
+            .iter()
+            .zip(kept)
+            .filter(|(_, &kept)| !kept)
+            .map(|((number, text), _)| (*number, text.as_str()))
+            .collect()
+    };
+    (left(before, &kept.0), left(after, &kept.1))
+}
+
+// mark the lines of a longest common subsequence of `a` and `b`. Myers' linear
+// space diff: the middle of a shortest edit script splits the problem in two,
+// so only a couple of rows are kept however long the files are
+fn common(a: &[&str], b: &[&str], in_a: &mut [bool], in_b: &mut [bool]) {
+    // lines the same at either end are in it, whatever is between them
+    let start = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let end = a[start..]
+        .iter()
+        .rev()
+        .zip(b[start..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    in_a[..start].iter_mut().for_each(|kept| *kept = true);
+    in_b[..start].iter_mut().for_each(|kept| *kept = true);
+    in_a[a.len() - end..]
+        .iter_mut()
+        .for_each(|kept| *kept = true);
+    in_b[b.len() - end..]
+        .iter_mut()
+        .for_each(|kept| *kept = true);
+
+    let (a, b) = (&a[start..a.len() - end], &b[start..b.len() - end]);
+    let in_a = &mut in_a[start..start + a.len()];
+    let in_b = &mut in_b[start..start + b.len()];
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+
+    let (x, y, u, v) = middle_snake(a, b);
+    in_a[x..u].iter_mut().for_each(|kept| *kept = true);
+    in_b[y..v].iter_mut().for_each(|kept| *kept = true);
+    common(&a[..x], &b[..y], &mut in_a[..x], &mut in_b[..y]);
+    common(&a[u..], &b[v..], &mut in_a[u..], &mut in_b[v..]);
+}
+
+// the run of equal lines `a[x..u]`, `b[y..v]` in the middle of a shortest edit
+// script, found by searching from both ends until the two searches meet
+fn middle_snake(a: &[&str], b: &[&str]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // the furthest `x` reached on each diagonal `k = x - y`, forwards and
+    // backwards from the ends, offset so that `k` of -max - 1 is at 0
+    let mut forward = vec![0; 2 * max as usize + 3];
+    let mut backward = vec![0; 2 * max as usize + 3];
+    let at = |k: isize| (k + max + 1) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x;
+            // the backward search is one step behind
+            if odd && (delta - k).abs() < d && x + backward[at(delta - k)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, (x - k) as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)]
+            } else {
+                backward[at(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[(n - 1 - x) as usize] == b[(m - 1 - x + k) as usize] {
+                x += 1;
+            }
+            backward[at(k)] = x;
+            if !odd && (delta - k).abs() <= d && x + forward[at(delta - k)] >= n {
+                let (u, v) = (n - x0, m - y0);
+                return (
+                    (n - x) as usize,
+                    (m - x + k) as usize,
+                    u as usize,
+                    v as usize,
+                );
+            }
+        }
+    }
+    unreachable!("an edit script is never longer than both files together")
+}
+
+type Place<'a> = (&'a Path, usize, &'a str);
+
+// turn every removed line that has an added twin into a move, first come first
+// served, and hand back the lines left over
+fn pair_moves<'a>(
+    gone: Vec<Place<'a>>,
+    came: Vec<Place<'a>>,
+    changes: &mut Vec<Change>,
+) -> (Vec<Place<'a>>, Vec<Place<'a>>) {
+    let mut arrivals: HashMap<&str, Vec<Place>> = HashMap::new();
+    for place in came.iter().rev() {
+        arrivals.entry(place.2.trim()).or_default().push(*place);
+    }
+
+    let mut left = Vec::new();
+    let mut paired = HashSet::new();
+    for (path, line, text) in gone {
+        match arrivals.get_mut(text.trim()).and_then(Vec::pop) {
+            Some(to) => {
+                paired.insert(to);
+                changes.push(Change::Moved {
+                    from: (path.to_path_buf(), line),
+                    to: (to.0.to_path_buf(), to.1),
+                    text: to.2.to_string(),
+                });
+            }
+            None => left.push((path, line, text)),
+        }
+    }
+    let came = came
+        .into_iter()
+        .filter(|place| !paired.contains(place))
+        .collect();
+    (left, came)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(files: &[(&str, &[(usize, &str)])]) -> Found {
+        files
+            .iter()
+            .map(|(path, lines)| {
+                let lines = lines.iter().map(|(n, t)| (*n, t.to_string())).collect();
+                (PathBuf::from(path), lines)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn other_options_are_rejected() {
+        let run = |args: &[&str]| {
+            let args = ["diff"].iter().chain(args).chain(&["q", "old", "new"]);
+            run(Config::new(args.map(|s| s.to_string())).unwrap())
+                .err()
+                .map(|e| e.to_string())
+        };
+        assert_eq!(
+            Some(String::from("diff can't be used with --matcher")),
+            run(&["--matcher", "nope"])
+        );
+        assert_eq!(
+            Some(String::from(
+                "diff can't be used with --json-path, --max-total"
+            )),
+            run(&["--json-path", ".", "--max-total", "3"])
+        );
+        assert!(run(&["--scope", "code"]).is_some());
+        assert!(run(&["--format", "vimgrep"]).is_some());
+    }
+
+    #[test]
+    fn shifted_lines_are_unchanged() {
+        let old = found(&[("a.rs", &[(3, "old_api(1);"), (9, "old_api(2);")])]);
+        // lines added above, and one call re-indented
+        let new = found(&[("a.rs", &[(13, "    old_api(1);"), (19, "old_api(2);")])]);
+        assert!(compare(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn added_removed_and_moved() {
+        let old = found(&[
+            (
+                "a.rs",
+                &[(1, "old_api(1);"), (2, "old_api(2);"), (5, "old_api(3);")],
+            ),
+            ("gone.rs", &[(4, "old_api(4);")]),
+        ]);
+        let new = found(&[
+            (
+                "a.rs",
+                &[(1, "old_api(2);"), (8, "old_api(1);"), (9, "old_api(5);")],
+            ),
+            ("b.rs", &[(2, "old_api(4);")]),
+        ]);
+
+        let path = PathBuf::from;
+        assert_eq!(
+            vec![
+                Change::Removed {
+                    path: path("a.rs"),
+                    line: 5,
+                    text: String::from("old_api(3);"),
+                },
+                Change::Moved {
+                    from: (path("a.rs"), 1),
+                    to: (path("a.rs"), 8),
+                    text: String::from("old_api(1);"),
+                },
+                Change::Added {
+                    path: path("a.rs"),
+                    line: 9,
+                    text: String::from("old_api(5);"),
+                },
+                Change::Moved {
+                    from: (path("gone.rs"), 4),
+                    to: (path("b.rs"), 2),
+                    text: String::from("old_api(4);"),
+                },
+            ],
+            compare(&old, &new)
+        );
+    }
+
+    #[test]
+    fn long_files() {
+        // 60k matching lines on each side, a table of them all would take gigabytes
+        let lines = |n: usize, skip: usize| -> Vec<(usize, String)> {
+            (0..n)
+                .filter(|&i| i != skip)
+                .map(|i| (i + 1, format!("call({});", i)))
+                .collect()
+        };
+        let old = lines(60_000, 100);
+        let new = lines(60_000, 50_000);
+        let (gone, came) = unmatched(&old, &new);
+        assert_eq!(vec![(50_001, "call(50000);")], gone);
+        assert_eq!(vec![(101, "call(100);")], came);
+    }
+}
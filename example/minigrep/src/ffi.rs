@@ -0,0 +1,403 @@
+//! the search library for C and C++, built into `libminigrep.so` (`.dylib`, `.dll`)
+//!
+//! the functions follow the `#[no_mangle] pub extern "C"` pattern from
+//! 24_advanced_trait.rs, the declarations are in `include/minigrep.h`.
+//! that header is generated from this file, run
+//! `MINIGREP_BLESS=1 cargo test header` after changing anything here.
+//!
+//! a query is compiled once and can search any number of buffers. a search
+//! returns every match with its line, to be walked with `minigrep_matches_next`.
+//! everything handed out must be given back to its `_free` function
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+use crate::regex::Regex;
+
+/// match without regard to case
+pub const MINIGREP_IGNORE_CASE: u32 = 1;
+/// the query is a regular expression instead of plain text
+pub const MINIGREP_REGEX: u32 = 2;
+
+/// a compiled query
+pub struct MinigrepQuery {
+    pattern: Regex,
+}
+
+/// one match, all offsets are bytes into the searched buffer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MinigrepMatch {
+    /// 1-based line number
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    /// the whole line the match is on, without its line break
+    pub line_start: usize,
+    pub line_end: usize,
+}
+
+/// the matches of one search
+pub struct MinigrepMatches {
+    matches: Vec<MinigrepMatch>,
+    next: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(msg: &str) {
+    // an interior NUL can't be passed on, cut the message there
+    let msg = msg.split('\0').next().unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(msg).unwrap_or_default());
+}
+
+/// why the last call on this thread that returned NULL failed.
+/// the string belongs to the library and stays valid until the next failing call
+#[no_mangle]
+pub extern "C" fn minigrep_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// compile `pattern` with any of the `MINIGREP_*` flags, NULL when it's invalid.
+///
+/// # Safety
+///
+/// `pattern` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_query_new(
+    pattern: *const c_char,
+    flags: u32,
+) -> *mut MinigrepQuery {
+    if pattern.is_null() {
+        set_error("pattern is NULL");
+        return ptr::null_mut();
+    }
+    let pattern = match CStr::from_ptr(pattern).to_str() {
+        Ok(pattern) => pattern,
+        Err(_) => {
+            set_error("pattern isn't UTF-8");
+            return ptr::null_mut();
+        }
+    };
+
+    let ignore_case = flags & MINIGREP_IGNORE_CASE != 0;
+    let pattern = if flags & MINIGREP_REGEX != 0 {
+        match Regex::new(pattern, ignore_case) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                set_error(&e.to_string());
+                return ptr::null_mut();
+            }
+        }
+    } else {
+        Regex::literal(pattern, ignore_case)
+    };
+    Box::into_raw(Box::new(MinigrepQuery { pattern }))
+}
+
+/// # Safety
+///
+/// `query` must come from `minigrep_query_new` and not be freed already, or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_query_free(query: *mut MinigrepQuery) {
+    if !query.is_null() {
+        drop(Box::from_raw(query));
+    }
+}
+
+/// search the `len` bytes at `buf`, which must be UTF-8, line by line.
+/// NULL when the text isn't UTF-8
+///
+/// # Safety
+///
+/// `query` must be a live query and `buf` point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_search(
+    query: *const MinigrepQuery,
+    buf: *const u8,
+    len: usize,
+) -> *mut MinigrepMatches {
+    if query.is_null() || (buf.is_null() && len > 0) {
+        set_error("query or buffer is NULL");
+        return ptr::null_mut();
+    }
+    let bytes = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(buf, len)
+    };
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            set_error(&e.to_string());
+            return ptr::null_mut();
+        }
+    };
+
+    let matches = find_all(&(*query).pattern, text);
+    Box::into_raw(Box::new(MinigrepMatches { matches, next: 0 }))
+}
+
+// every match of every line, the same lines `search_pattern` reports
+fn find_all(pattern: &Regex, text: &str) -> Vec<MinigrepMatch> {
+    let mut matches = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_start = line.as_ptr() as usize - text.as_ptr() as usize;
+        for (start, end) in pattern.find_iter(line) {
+            matches.push(MinigrepMatch {
+                line: index + 1,
+                start: line_start + start,
+                end: line_start + end,
+                line_start,
+                line_end: line_start + line.len(),
+            });
+        }
+    }
+    matches
+}
+
+/// how many matches the search found in all
+///
+/// # Safety
+///
+/// `matches` must come from `minigrep_search` and not be freed already
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_matches_count(matches: *const MinigrepMatches) -> usize {
+    if matches.is_null() {
+        return 0;
+    }
+    (*matches).matches.len()
+}
+
+/// copy the next match into `out`, false once there are no more
+///
+/// # Safety
+///
+/// `matches` must be live and `out` point to writable memory for one `MinigrepMatch`
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_matches_next(
+    matches: *mut MinigrepMatches,
+    out: *mut MinigrepMatch,
+) -> bool {
+    if matches.is_null() || out.is_null() {
+        return false;
+    }
+    let matches = &mut *matches;
+    match matches.matches.get(matches.next) {
+        Some(found) => {
+            *out = *found;
+            matches.next += 1;
+            true
+        }
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// `matches` must come from `minigrep_search` and not be freed already, or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn minigrep_matches_free(matches: *mut MinigrepMatches) {
+    if !matches.is_null() {
+        drop(Box::from_raw(matches));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `include/minigrep.h`, written from the items above the tests in this file
+    fn header() -> String {
+        let source = include_str!("ffi.rs");
+        let source = &source[..source.find("\n#[cfg(test)]").unwrap_or(source.len())];
+        let mut out = String::from(
+            "/* generated from src/ffi.rs, don't edit by hand */\n\
+             #ifndef MINIGREP_H\n#define MINIGREP_H\n\n\
+             #include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n\
+             #ifdef __cplusplus\nextern \"C\" {\n#endif\n",
+        );
+
+        let mut docs: Vec<&str> = Vec::new();
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if let Some(doc) = line.strip_prefix("///") {
+                docs.push(doc.trim());
+                continue;
+            }
+            if line.starts_with("#[") {
+                continue;
+            }
+
+            // the `# Safety` section is for Rust callers
+            let docs_end = docs
+                .iter()
+                .position(|d| d.starts_with("# "))
+                .unwrap_or(docs.len());
+            let comment: String = docs[..docs_end]
+                .iter()
+                .filter(|d| !d.is_empty())
+                .map(|d| format!("// {}\n", d))
+                .collect();
+
+            if let Some(rest) = line.strip_prefix("pub const ") {
+                // `NAME: u32 = 1;`
+                let (name, value) = rest.split_once(':').unwrap();
+                let value = value
+                    .split('=')
+                    .nth(1)
+                    .unwrap()
+                    .trim()
+                    .trim_end_matches(';');
+                out += &format!("\n{}#define {} {}u\n", comment, name, value);
+            } else if let Some(rest) = line.strip_prefix("pub struct ") {
+                let name = rest.split_whitespace().next().unwrap();
+                let mut fields = String::new();
+                for field in lines.by_ref() {
+                    let field = field.trim();
+                    if field == "}" {
+                        break;
+                    }
+                    if let Some(doc) = field.strip_prefix("///") {
+                        fields += &format!("    // {}\n", doc.trim());
+                    } else if let Some(field) = field.strip_prefix("pub ") {
+                        let (field, ty) = field.trim_end_matches(',').split_once(": ").unwrap();
+                        fields += &format!("    {} {};\n", c_type(ty), field);
+                    }
+                }
+                if fields.is_empty() {
+                    // only ever handled through pointers
+                    out += &format!("\n{}typedef struct {} {};\n", comment, name, name);
+                } else {
+                    out += &format!(
+                        "\n{}typedef struct {} {{\n{}}} {};\n",
+                        comment, name, fields, name
+                    );
+                }
+            } else if line.starts_with("pub extern \"C\" fn ")
+                || line.starts_with("pub unsafe extern \"C\" fn ")
+            {
+                // the signature can be spread over several lines
+                let mut signature = line.to_string();
+                while !signature.ends_with('{') {
+                    signature.push(' ');
+                    signature.push_str(lines.next().unwrap().trim());
+                }
+                out += &format!("\n{}{};\n", comment, c_function(&signature));
+            }
+            docs.clear();
+        }
+
+        out += "\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n";
+        out
+    }
+
+    // `pub unsafe extern "C" fn name(a: *const u8, b: usize) -> bool {` in C
+    fn c_function(signature: &str) -> String {
+        let start = signature.find("fn ").unwrap() + 3;
+        let open = signature.find('(').unwrap();
+        let close = signature.rfind(')').unwrap();
+        let name = &signature[start..open];
+        let params: Vec<String> = signature[open + 1..close]
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, ty) = param.split_once(": ").unwrap();
+                format!("{} {}", c_type(ty), name)
+            })
+            .collect();
+        let ret = signature[close + 1..]
+            .trim()
+            .trim_end_matches('{')
+            .trim()
+            .strip_prefix("-> ")
+            .map_or("void", |ty| ty.trim());
+        let params = if params.is_empty() {
+            String::from("void")
+        } else {
+            params.join(", ")
+        };
+        format!("{} {}({})", c_type(ret), name, params)
+    }
+
+    fn c_type(ty: &str) -> String {
+        let ty = ty.trim();
+        if let Some(inner) = ty.strip_prefix("*const ") {
+            return format!("const {}*", c_type(inner));
+        }
+        if let Some(inner) = ty.strip_prefix("*mut ") {
+            return format!("{}*", c_type(inner));
+        }
+        match ty {
+            "void" => "void",
+            "c_char" => "char",
+            "u8" => "uint8_t",
+            "u32" => "uint32_t",
+            "usize" => "size_t",
+            "bool" => "bool",
+            name => name,
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn search_through_the_c_functions() {
+        unsafe {
+            let query = minigrep_query_new(b"n[a-z]+y\0".as_ptr() as *const c_char, MINIGREP_REGEX);
+            assert!(!query.is_null());
+            let text = "I'm nobody! Who are you?\nAre you nobody, too? nosy\n";
+            let matches = minigrep_search(query, text.as_ptr(), text.len());
+            assert_eq!(3, minigrep_matches_count(matches));
+
+            let mut found = MinigrepMatch::default();
+            let mut all = Vec::new();
+            while minigrep_matches_next(matches, &mut found) {
+                all.push((found.line, &text[found.start..found.end]));
+            }
+            assert_eq!(vec![(1, "nobody"), (2, "nobody"), (2, "nosy")], all);
+            assert_eq!(
+                &text[found.line_start..found.line_end],
+                "Are you nobody, too? nosy"
+            );
+
+            minigrep_matches_free(matches);
+            minigrep_query_free(query);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let query = minigrep_query_new(b"(\0".as_ptr() as *const c_char, MINIGREP_REGEX);
+            assert!(query.is_null());
+            let error = CStr::from_ptr(minigrep_last_error()).to_str().unwrap();
+            assert!(!error.is_empty());
+
+            let query = minigrep_query_new(b"x\0".as_ptr() as *const c_char, 0);
+            let bad = [0x66, 0xff];
+            assert!(minigrep_search(query, bad.as_ptr(), bad.len()).is_null());
+            minigrep_query_free(query);
+        }
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/include/minigrep.h");
+        let generated = header();
+        if std::env::var_os("MINIGREP_BLESS").is_some() {
+            std::fs::write(path, &generated).unwrap();
+        }
+        let current = std::fs::read_to_string(path).unwrap_or_default();
+        assert!(
+            current == generated,
+            "include/minigrep.h is out of date, run `MINIGREP_BLESS=1 cargo test header`"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! `--follow`: keep reading lines as they are appended to a file, like `tail -f`
+//!
+//! a followed file is read from where it ended when the search started. the
+//! path is checked on every poll, so log rotation is picked up either way it's
+//! done: a file truncated in place is read again from the start, and a path that
+//! now names another file (renamed away and created again) is reopened once the
+//! last lines of the old one are read. a file that doesn't exist yet is waited for
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// how long to wait before looking for new lines again
+pub const POLL: Duration = Duration::from_millis(100);
+
+pub struct Follower {
+    pub path: PathBuf,
+    file: Option<File>,
+    // the file the path named when it was opened
+    id: Option<(u64, u64)>,
+    // bytes read so far
+    pos: u64,
+    // the number of the last complete line read
+    line: usize,
+    // the start of a line that hasn't been ended yet
+    partial: Vec<u8>,
+}
+
+// new lines and their numbers
+type Lines = Vec<(usize, String)>;
+
+impl Follower {
+    /// follow `path` from its current end. lines already in it are only counted
+    /// when `count_lines` asks for it, to number the new ones. a last line that
+    /// isn't finished yet is shown whole once it is
+    pub fn open(path: &Path, count_lines: bool) -> io::Result<Follower> {
+        let mut follower = Follower {
+            path: path.to_path_buf(),
+            file: None,
+            id: None,
+            pos: 0,
+            line: 0,
+            partial: Vec::new(),
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(follower),
+            Err(e) => return Err(e),
+        };
+        let meta = file.metadata()?;
+        if meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "is a directory, --follow needs files",
+            ));
+        }
+        if count_lines {
+            let mut buf = [0; 64 * 1024];
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                let read = &buf[..n];
+                follower.line += read.iter().filter(|&&b| b == b'\n').count();
+                follower.pos += n as u64;
+                if let Some(end) = read.iter().rposition(|&b| b == b'\n') {
+                    follower.partial.clear();
+                    follower.partial.extend_from_slice(&read[end + 1..]);
+                } else {
+                    follower.partial.extend_from_slice(read);
+                }
+            }
+        } else {
+            // back to the start of the last line, looking for the break before it
+            let mut start = file.seek(SeekFrom::End(0))?;
+            let mut buf = [0; 4096];
+            while start > 0 {
+                let n = start.min(buf.len() as u64);
+                file.seek(SeekFrom::Start(start - n))?;
+                file.read_exact(&mut buf[..n as usize])?;
+                match buf[..n as usize].iter().rposition(|&b| b == b'\n') {
+                    Some(end) => {
+                        start = start - n + end as u64 + 1;
+                        break;
+                    }
+                    None => start -= n,
+                }
+            }
+            follower.pos = file.seek(SeekFrom::Start(start))?;
+        }
+        follower.id = identity(&meta);
+        follower.file = Some(file);
+        Ok(follower)
+    }
+
+    /// the lines completed since the last poll, and how many bytes were read
+    pub fn poll(&mut self) -> io::Result<(u64, Lines)> {
+        let mut lines = Vec::new();
+        let mut bytes = 0;
+
+        if self.file.is_none() {
+            match File::open(&self.path) {
+                Ok(file) => {
+                    self.id = identity(&file.metadata()?);
+                    self.file = Some(file);
+                    self.pos = 0;
+                    self.line = 0;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, lines)),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // looked at before reading: lines written to the old file until now are
+        // read below, and anything that lands in it after that by the read again
+        // before it's let go, as logrotate leaves the writer on it for a while
+        let replaced = match fs::metadata(&self.path) {
+            Ok(meta) => identity(&meta) != self.id,
+            // renamed away and not created again yet, keep reading the old one
+            Err(_) => false,
+        };
+
+        if let Some(file) = &mut self.file {
+            // shorter than what was read already: truncated, start over
+            if file.metadata()?.len() < self.pos {
+                file.seek(SeekFrom::Start(0))?;
+                self.pos = 0;
+                self.line = 0;
+                self.partial.clear();
+            }
+            bytes += self.read_new(&mut lines)?;
+        }
+
+        // the path names another file now, the old one is done with
+        if replaced {
+            bytes += self.read_new(&mut lines)?;
+            if !self.partial.is_empty() {
+                self.line += 1;
+                let rest = std::mem::take(&mut self.partial);
+                lines.push((self.line, String::from_utf8_lossy(&rest).into_owned()));
+            }
+            self.file = None;
+        }
+        Ok((bytes, lines))
+    }
+
+    // read the open file to its end, and how many bytes that was
+    fn read_new(&mut self, lines: &mut Lines) -> io::Result<u64> {
+        let mut new = Vec::new();
+        if let Some(file) = &mut self.file {
+            file.read_to_end(&mut new)?;
+        }
+        self.pos += new.len() as u64;
+        self.split(&new, lines);
+        Ok(new.len() as u64)
+    }
+
+    // add the lines `new` completes, keeping an unfinished one for later
+    fn split(&mut self, new: &[u8], lines: &mut Lines) {
+        self.partial.extend_from_slice(new);
+        let end = match self.partial.iter().rposition(|&b| b == b'\n') {
+            Some(end) => end,
+            None => return,
+        };
+        let rest = self.partial.split_off(end + 1);
+        let done = std::mem::replace(&mut self.partial, rest);
+        // bytes that aren't UTF-8 are shown as U+FFFD instead of stopping
+        for line in String::from_utf8_lossy(&done).lines() {
+            self.line += 1;
+            lines.push((self.line, line.to_string()));
+        }
+    }
+}
+
+// the device and inode, which change when a new file takes the path
+#[cfg(unix)]
+fn identity(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+// elsewhere only truncation is noticed
+#[cfg(not(unix))]
+fn identity(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn texts(lines: Lines) -> Vec<String> {
+        lines.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn appended_lines() {
+        let dir = std::env::temp_dir().join(format!("minigrep-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "old 1\nold 2\n").unwrap();
+
+        let mut follower = Follower::open(&path, true).unwrap();
+        assert!(follower.poll().unwrap().1.is_empty());
+        // half a line waits for the rest of it
+        append(&path, "new 3\nnew ");
+        assert_eq!(vec![(3, String::from("new 3"))], follower.poll().unwrap().1);
+        append(&path, "4\n");
+        assert_eq!(vec![(4, String::from("new 4"))], follower.poll().unwrap().1);
+
+        // truncated in place
+        fs::write(&path, "").unwrap();
+        append(&path, "after truncate\n");
+        assert_eq!(
+            vec![(1, String::from("after truncate"))],
+            follower.poll().unwrap().1
+        );
+
+        // renamed away and created again. the writer still has the old file
+        // open for a while, and its last line counts even without a line break
+        append(&path, "last\n");
+        let old = dir.join("app.log.1");
+        fs::rename(&path, &old).unwrap();
+        append(&old, "after rename\nunfinished");
+        fs::write(&path, "rotated\n").unwrap();
+        assert_eq!(
+            vec!["last", "after rename", "unfinished"],
+            texts(follower.poll().unwrap().1)
+        );
+        assert_eq!(vec!["rotated"], texts(follower.poll().unwrap().1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unfinished_last_line() {
+        let dir = std::env::temp_dir().join(format!("minigrep-follow-half-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for count_lines in [false, true] {
+            let path = dir.join(format!("{}.log", count_lines));
+            fs::write(&path, "old 1\nhalf").unwrap();
+            let mut follower = Follower::open(&path, count_lines).unwrap();
+            assert!(follower.poll().unwrap().1.is_empty());
+            append(&path, " done\n");
+            let number = if count_lines { 2 } else { 1 };
+            assert_eq!(
+                vec![(number, String::from("half done"))],
+                follower.poll().unwrap().1
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_file() {
+        let path = std::env::temp_dir().join(format!("minigrep-follow-new-{}", std::process::id()));
+        let mut follower = Follower::open(&path, false).unwrap();
+        assert!(follower.poll().unwrap().1.is_empty());
+        fs::write(&path, "first\n").unwrap();
+        assert_eq!(vec!["first"], texts(follower.poll().unwrap().1));
+        fs::remove_file(&path).unwrap();
+    }
+}
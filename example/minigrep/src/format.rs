@@ -0,0 +1,171 @@
+//! `--format`: one record per match, with the location editors look for
+//!
+//! - `vimgrep`, `path:line:col:text`, what `:grep` reads with `grepformat=%f:%l:%c:%m`
+//! - `quickfix`, `path:line:col: text`, the compiler style of vim's default `errorformat`
+//! - `emacs`, `path:line.col: text`, the GNU style `compilation-mode` recognises
+//! - a template such as `{path}:{line}:{col}: {text}`, also with `{match}`,
+//!   where `{{` and `}}` stand for the braces themselves
+//!
+//! lines and columns count from 1. vim counts columns in bytes and Emacs in
+//! characters, so `emacs` and `{col}` differ on lines that aren't ASCII: a
+//! template's `{col}` is in bytes, like vim's
+
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    Path,
+    Line,
+    Col,
+    // the whole line the match is on
+    Text,
+    Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Vimgrep,
+    Quickfix,
+    Emacs,
+    Custom(Vec<Piece>),
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Format, String> {
+        match text {
+            "vimgrep" => return Ok(Format::Vimgrep),
+            "quickfix" => return Ok(Format::Quickfix),
+            "emacs" => return Ok(Format::Emacs),
+            _ if !text.contains('{') => {
+                return Err(format!(
+                    "unknown format '{}', use vimgrep, quickfix, emacs or a template such as '{{path}}:{{line}}: {{text}}'",
+                    text
+                ))
+            }
+            _ => {}
+        }
+
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                literal.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+            if c != '{' {
+                if c == '}' {
+                    return Err(format!("'}}' without a '{{' in format '{}'", text));
+                }
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("unclosed '{{' in format '{}'", text))?;
+            let piece = match &rest[1..end] {
+                "path" => Piece::Path,
+                "line" => Piece::Line,
+                "col" => Piece::Col,
+                "text" => Piece::Text,
+                "match" => Piece::Match,
+                other => {
+                    return Err(format!(
+                        "unknown field '{{{}}}' in format, use {{path}}, {{line}}, {{col}}, {{text}} or {{match}}",
+                        other
+                    ))
+                }
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(piece);
+            rest = &rest[end + 1..];
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Format::Custom(pieces))
+    }
+
+    /// write the record of the match at bytes `start..end` of `line`
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        path: &str,
+        number: usize,
+        line: &str,
+        (start, end): (usize, usize),
+    ) -> io::Result<()> {
+        let col = start + 1;
+        match self {
+            Format::Vimgrep => writeln!(out, "{}:{}:{}:{}", path, number, col, line),
+            Format::Quickfix => writeln!(out, "{}:{}:{}: {}", path, number, col, line),
+            Format::Emacs => {
+                let col = line[..start].chars().count() + 1;
+                writeln!(out, "{}:{}.{}: {}", path, number, col, line)
+            }
+            Format::Custom(pieces) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Literal(text) => write!(out, "{}", text)?,
+                        Piece::Path => write!(out, "{}", path)?,
+                        Piece::Line => write!(out, "{}", number)?,
+                        Piece::Col => write!(out, "{}", col)?,
+                        Piece::Text => write!(out, "{}", line)?,
+                        Piece::Match => write!(out, "{}", &line[start..end])?,
+                    }
+                }
+                writeln!(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(format: &str, line: &str, at: (usize, usize)) -> String {
+        let mut out = Vec::new();
+        Format::parse(format)
+            .unwrap()
+            .write(&mut out, "src/main.rs", 7, line, at)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn named_formats() {
+        let line = "    let café = todo!();";
+        // the match is `todo`, after a two byte 'é'
+        let at = (16, 20);
+        assert_eq!(
+            "src/main.rs:7:17:    let café = todo!();\n",
+            record("vimgrep", line, at)
+        );
+        assert_eq!(
+            "src/main.rs:7:17:     let café = todo!();\n",
+            record("quickfix", line, at)
+        );
+        assert_eq!(
+            "src/main.rs:7.16:     let café = todo!();\n",
+            record("emacs", line, at)
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            "{src/main.rs} 7/3 [c] abc\n",
+            record("{{{path}}} {line}/{col} [{match}] {text}", "abc", (2, 3))
+        );
+        assert!(Format::parse("json").is_err());
+        assert!(Format::parse("{path").is_err());
+        assert!(Format::parse("{file}:{line}").is_err());
+        assert!(Format::parse("{path}}").is_err());
+    }
+}
@@ -0,0 +1,643 @@
+//! `--git-history`: read the objects of a `.git` directory without git itself
+//!
+//! objects are found loose under `objects/` (each one a zlib stream) or in a
+//! packfile, through the version 2 `.idx` next to it. packed objects may be
+//! stored as a delta against another object in the same pack or against any
+//! object by id; deltas are applied here too. only SHA-1 repositories are read
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::inflate;
+
+// a delta may be based on another delta, git itself stops at 50 by default
+const MAX_DELTA_DEPTH: usize = 10_000;
+
+#[derive(Debug)]
+pub struct GitError(String);
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for GitError {}
+
+fn error<T>(msg: impl Into<String>) -> Result<T, GitError> {
+    Err(GitError(msg.into()))
+}
+
+/// the id of an object, the SHA-1 of its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Oid([u8; 20]);
+
+impl Oid {
+    pub fn from_hex(hex: &str) -> Option<Oid> {
+        if hex.len() != 40 {
+            return None;
+        }
+        let mut id = [0; 20];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        Some(Oid(id))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Oid> {
+        let mut id = [0; 20];
+        id.copy_from_slice(bytes.get(..20)?);
+        Some(Oid(id))
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl Kind {
+    fn parse(name: &[u8]) -> Option<Kind> {
+        match name {
+            b"commit" => Some(Kind::Commit),
+            b"tree" => Some(Kind::Tree),
+            b"blob" => Some(Kind::Blob),
+            b"tag" => Some(Kind::Tag),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub tree: Oid,
+    pub parents: Vec<Oid>,
+    // committer time, seconds since the Unix epoch
+    pub time: i64,
+}
+
+// a packfile and its index, both read whole
+struct Pack {
+    name: PathBuf,
+    index: Vec<u8>,
+    data: Vec<u8>,
+}
+
+pub struct Repo {
+    git_dir: PathBuf,
+    packs: Vec<Pack>,
+    // commits whose parents were cut off by a shallow clone
+    shallow: HashSet<Oid>,
+}
+
+impl Repo {
+    /// the repository `path` is in, looking upwards like git does
+    pub fn discover(path: &Path) -> Result<Repo, GitError> {
+        let start = path
+            .canonicalize()
+            .map_err(|e| GitError(format!("{}: {}", path.display(), e)))?;
+        for dir in start.ancestors() {
+            let dot_git = dir.join(".git");
+            if dot_git.is_dir() {
+                return Repo::open(&dot_git);
+            }
+            // worktrees and submodules have a file pointing at the real directory
+            if let Ok(text) = fs::read_to_string(&dot_git) {
+                if let Some(target) = text.trim().strip_prefix("gitdir: ") {
+                    return Repo::open(&dir.join(target));
+                }
+            }
+            // a bare repository
+            if dir.join("HEAD").is_file() && dir.join("objects").is_dir() {
+                return Repo::open(dir);
+            }
+        }
+        error(format!("{} is not in a git repository", path.display()))
+    }
+
+    pub fn open(git_dir: &Path) -> Result<Repo, GitError> {
+        let mut packs = Vec::new();
+        let pack_dir = git_dir.join("objects/pack");
+        if let Ok(entries) = fs::read_dir(&pack_dir) {
+            let mut names: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+                .collect();
+            names.sort();
+            for name in names {
+                let read = |path: &Path| {
+                    fs::read(path).map_err(|e| GitError(format!("{}: {}", path.display(), e)))
+                };
+                let index = read(&name)?;
+                let data = read(&name.with_extension("pack"))?;
+                if !index.starts_with(b"\xfftOc\0\0\0\x02") {
+                    return error(format!(
+                        "{}: only version 2 indexes are read",
+                        name.display()
+                    ));
+                }
+                packs.push(Pack { name, index, data });
+            }
+        }
+
+        let shallow = fs::read_to_string(git_dir.join("shallow"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(Oid::from_hex)
+            .collect();
+        Ok(Repo {
+            git_dir: git_dir.to_path_buf(),
+            packs,
+            shallow,
+        })
+    }
+
+    /// the commit HEAD points at, through any symbolic refs
+    pub fn head(&self) -> Result<Oid, GitError> {
+        let mut name = String::from("HEAD");
+        // a ref can point at another ref, but not forever
+        for _ in 0..10 {
+            let target = match fs::read_to_string(self.git_dir.join(&name)) {
+                Ok(text) => text.trim().to_string(),
+                Err(_) => self.packed_ref(&name)?,
+            };
+            match target.strip_prefix("ref: ") {
+                Some(next) => name = next.to_string(),
+                None => {
+                    return Oid::from_hex(&target)
+                        .ok_or_else(|| GitError(format!("{} is not an object id", name)))
+                }
+            }
+        }
+        error("HEAD is a loop of symbolic refs")
+    }
+
+    fn packed_ref(&self, name: &str) -> Result<String, GitError> {
+        let text = fs::read_to_string(self.git_dir.join("packed-refs")).unwrap_or_default();
+        text.lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, ref_name)| *ref_name == name)
+            .map(|(id, _)| id.to_string())
+            .ok_or_else(|| GitError(format!("no ref {}, is the repository empty?", name)))
+    }
+
+    /// the kind and contents of an object
+    pub fn read(&self, id: Oid) -> Result<(Kind, Vec<u8>), GitError> {
+        if let Some(object) = self.read_loose(id) {
+            return object;
+        }
+        match self.find_packed(id) {
+            Some((pack, offset)) => self.read_packed(pack, offset).map_err(|e| {
+                let name = self.packs[pack].name.display();
+                GitError(format!("{}: object {}: {}", name, id, e))
+            }),
+            None => error(format!("object {} is missing", id)),
+        }
+    }
+
+    // `None` when the object isn't stored loose
+    fn read_loose(&self, id: Oid) -> Option<Result<(Kind, Vec<u8>), GitError>> {
+        let hex = id.to_string();
+        let loose = self.git_dir.join("objects").join(&hex[..2]).join(&hex[2..]);
+        let bytes = fs::read(&loose).ok()?;
+        let object = match inflate::zlib_decompress(&bytes) {
+            Ok(object) => object,
+            Err(e) => return Some(error(format!("object {}: {}", id, e))),
+        };
+        // "<kind> <size>\0<contents>"
+        let nul = object.iter().position(|&b| b == 0);
+        let kind = nul.and_then(|nul| {
+            let header = &object[..nul];
+            let space = header.iter().position(|&b| b == b' ')?;
+            Kind::parse(&header[..space])
+        });
+        Some(match (kind, nul) {
+            (Some(kind), Some(nul)) => Ok((kind, object[nul + 1..].to_vec())),
+            _ => error(format!("object {} has a broken header", id)),
+        })
+    }
+
+    // the pack holding an object, and where in it
+    fn find_packed(&self, id: Oid) -> Option<(usize, usize)> {
+        self.packs
+            .iter()
+            .enumerate()
+            .find_map(|(i, pack)| find(&pack.index, id).map(|offset| (i, offset)))
+    }
+
+    // follow a chain of deltas down to a whole object, then apply them on the way back.
+    // bases named by id are followed in the same loop, so one chain can't get
+    // past the depth limit by going through them, or go round in a circle forever
+    fn read_packed(&self, mut pack: usize, mut offset: usize) -> Result<(Kind, Vec<u8>), GitError> {
+        let mut data = &self.packs[pack].data;
+        let mut deltas = Vec::new();
+
+        let (kind, mut object) = loop {
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return error("delta chain too long");
+            }
+            let (code, mut pos) = entry_header(data, offset)?;
+            match code {
+                1..=4 => {
+                    let kind = [Kind::Commit, Kind::Tree, Kind::Blob, Kind::Tag][code - 1];
+                    break (kind, unzip(data, pos)?);
+                }
+                // the base is further back in this pack
+                6 => {
+                    let mut byte = byte_at(data, pos)?;
+                    pos += 1;
+                    let mut back = usize::from(byte & 0x7f);
+                    while byte & 0x80 != 0 {
+                        byte = byte_at(data, pos)?;
+                        pos += 1;
+                        back = back
+                            .checked_add(1)
+                            .and_then(|back| back.checked_mul(1 << 7))
+                            .ok_or(GitError(String::from("delta base outside the pack")))?
+                            | usize::from(byte & 0x7f);
+                    }
+                    deltas.push(unzip(data, pos)?);
+                    offset = match offset.checked_sub(back) {
+                        Some(base) if back > 0 => base,
+                        _ => return error("delta base outside the pack"),
+                    };
+                }
+                // the base is named by id and may be anywhere
+                7 => {
+                    let base = Oid::from_bytes(data.get(pos..).unwrap_or_default())
+                        .ok_or(GitError(String::from("truncated pack")))?;
+                    deltas.push(unzip(data, pos + 20)?);
+                    if let Some(object) = self.read_loose(base) {
+                        break object?;
+                    }
+                    (pack, offset) = self
+                        .find_packed(base)
+                        .ok_or_else(|| GitError(format!("delta base {} is missing", base)))?;
+                    data = &self.packs[pack].data;
+                }
+                _ => return error(format!("unknown pack entry type {}", code)),
+            }
+        };
+
+        for delta in deltas.iter().rev() {
+            object = apply_delta(&object, delta)?;
+        }
+        Ok((kind, object))
+    }
+
+    pub fn commit(&self, id: Oid) -> Result<Commit, GitError> {
+        match self.read(id)? {
+            (Kind::Commit, data) => {
+                parse_commit(&data).ok_or_else(|| GitError(format!("commit {} is broken", id)))
+            }
+            _ => error(format!("{} is not a commit", id)),
+        }
+    }
+
+    /// every commit reachable from `head`, newest first by committer time
+    pub fn history(&self, head: Oid) -> Result<Vec<(Oid, Commit)>, GitError> {
+        let mut commits = Vec::new();
+        let mut seen = HashSet::new();
+        // read but not yet taken off the queue
+        let mut pending = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let first = self.commit(head)?;
+        queue.push((first.time, Reverse(head)));
+        pending.insert(head, first);
+        seen.insert(head);
+
+        while let Some((_, Reverse(id))) = queue.pop() {
+            let commit = pending.remove(&id).unwrap();
+            if !self.shallow.contains(&id) {
+                for &parent in &commit.parents {
+                    if seen.insert(parent) {
+                        let parent_commit = self.commit(parent)?;
+                        queue.push((parent_commit.time, Reverse(parent)));
+                        pending.insert(parent, parent_commit);
+                    }
+                }
+            }
+            commits.push((id, commit));
+        }
+        Ok(commits)
+    }
+
+    /// every file in a tree as (path, blob id), sorted by path. subtrees already
+    /// listed in `seen` aren't read again, most of a tree is the same from one
+    /// commit to the next
+    pub fn files(
+        &self,
+        tree: Oid,
+        seen: &mut HashMap<Oid, Rc<Vec<(String, Oid)>>>,
+    ) -> Result<Rc<Vec<(String, Oid)>>, GitError> {
+        if let Some(files) = seen.get(&tree) {
+            return Ok(Rc::clone(files));
+        }
+        let data = match self.read(tree)? {
+            (Kind::Tree, data) => data,
+            _ => return error(format!("{} is not a tree", tree)),
+        };
+
+        let mut files = Vec::new();
+        for (mode, name, id) in
+            parse_tree(&data).ok_or_else(|| GitError(format!("tree {} is broken", tree)))?
+        {
+            match mode {
+                "40000" => {
+                    for (path, blob) in self.files(id, seen)?.iter() {
+                        files.push((format!("{}/{}", name, path), *blob));
+                    }
+                }
+                // symbolic links and submodules hold no text of their own
+                "120000" | "160000" => {}
+                _ => files.push((name, id)),
+            }
+        }
+        let files = Rc::new(files);
+        seen.insert(tree, Rc::clone(&files));
+        Ok(files)
+    }
+}
+
+// the offset of `id` in a pack, from its version 2 index
+fn find(index: &[u8], id: Oid) -> Option<usize> {
+    let word = |at: usize| -> Option<u32> {
+        let bytes = index.get(at..at + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    // 256 running counts of the ids starting with each byte
+    let fanout = 8;
+    let first = usize::from(id.0[0]);
+    let lo = if first == 0 {
+        0
+    } else {
+        word(fanout + 4 * (first - 1))? as usize
+    };
+    let hi = word(fanout + 4 * first)? as usize;
+    let count = word(fanout + 4 * 255)? as usize;
+
+    let ids = fanout + 1024;
+    let mut lo = lo;
+    let mut hi = hi;
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let at = ids + 20 * mid;
+        match index.get(at..at + 20)?.cmp(&id.0[..]) {
+            std::cmp::Ordering::Less => lo = mid + 1,
+            std::cmp::Ordering::Greater => hi = mid,
+            std::cmp::Ordering::Equal => {
+                // after the ids come a crc for each, then the offsets
+                let offsets = ids + 24 * count;
+                let offset = word(offsets + 4 * mid)?;
+                if offset & 0x8000_0000 == 0 {
+                    return Some(offset as usize);
+                }
+                // packs over 2GB keep big offsets in a table of their own
+                let large = offsets + 4 * count + 8 * (offset & 0x7fff_ffff) as usize;
+                let bytes = index.get(large..large + 8)?;
+                let mut big = [0; 8];
+                big.copy_from_slice(bytes);
+                return usize::try_from(u64::from_be_bytes(big)).ok();
+            }
+        }
+    }
+    None
+}
+
+fn byte_at(data: &[u8], pos: usize) -> Result<u8, GitError> {
+    data.get(pos)
+        .copied()
+        .ok_or(GitError(String::from("truncated pack")))
+}
+
+// the type of a pack entry and where its data starts; the size is in the
+// zlib stream as well so it's skipped
+fn entry_header(data: &[u8], offset: usize) -> Result<(usize, usize), GitError> {
+    let mut byte = byte_at(data, offset)?;
+    let code = usize::from((byte >> 4) & 7);
+    let mut pos = offset + 1;
+    while byte & 0x80 != 0 {
+        byte = byte_at(data, pos)?;
+        pos += 1;
+    }
+    Ok((code, pos))
+}
+
+fn unzip(data: &[u8], pos: usize) -> Result<Vec<u8>, GitError> {
+    let stream = data.get(pos..).unwrap_or_default();
+    inflate::zlib_decompress(stream).map_err(|e| GitError(e.to_string()))
+}
+
+// a little-endian base 128 number as used in delta headers
+fn varint(delta: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos)?;
+        *pos += 1;
+        value |= usize::from(byte & 0x7f).checked_shl(shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+}
+
+/// rebuild an object from its base and a git delta: the two sizes, then
+/// instructions that either copy a range of the base or insert new bytes
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, GitError> {
+    let broken = || GitError(String::from("broken delta"));
+    let mut pos = 0;
+    let base_size = varint(delta, &mut pos).ok_or_else(broken)?;
+    let size = varint(delta, &mut pos).ok_or_else(broken)?;
+    if base_size != base.len() {
+        return Err(broken());
+    }
+
+    // the size is only a hint until the delta is seen to produce it
+    let mut out = Vec::with_capacity(size.min(delta.len().saturating_mul(128)));
+    while let Some(&op) = delta.get(pos) {
+        pos += 1;
+        if op & 0x80 != 0 {
+            // which bytes of the offset and size follow is in the low bits of `op`
+            let mut fields = [0usize; 2];
+            let mut bit = 0;
+            for (field, bytes) in fields.iter_mut().zip([4, 3]) {
+                for i in 0..bytes {
+                    if op & (1 << bit) != 0 {
+                        let byte = *delta.get(pos).ok_or_else(broken)?;
+                        pos += 1;
+                        *field |= usize::from(byte) << (8 * i);
+                    }
+                    bit += 1;
+                }
+            }
+            let [start, mut len] = fields;
+            if len == 0 {
+                len = 0x10000;
+            }
+            let copy = base.get(start..start + len).ok_or_else(broken)?;
+            out.extend_from_slice(copy);
+        } else if op > 0 {
+            let insert = delta.get(pos..pos + usize::from(op)).ok_or_else(broken)?;
+            out.extend_from_slice(insert);
+            pos += usize::from(op);
+        } else {
+            return Err(broken());
+        }
+    }
+    if out.len() != size {
+        return Err(broken());
+    }
+    Ok(out)
+}
+
+fn parse_commit(data: &[u8]) -> Option<Commit> {
+    let text = String::from_utf8_lossy(data);
+    let mut tree = None;
+    let mut parents = Vec::new();
+    let mut time = 0;
+    // the headers end at the first blank line, the message follows
+    for line in text.lines().take_while(|line| !line.is_empty()) {
+        if let Some(id) = line.strip_prefix("tree ") {
+            tree = Oid::from_hex(id);
+        } else if let Some(id) = line.strip_prefix("parent ") {
+            parents.push(Oid::from_hex(id)?);
+        } else if let Some(committer) = line.strip_prefix("committer ") {
+            // "Name <email> 1700000000 +0100"
+            let mut fields = committer.rsplit(' ');
+            fields.next();
+            time = fields.next()?.parse().ok()?;
+        }
+    }
+    Some(Commit {
+        tree: tree?,
+        parents,
+        time,
+    })
+}
+
+// the (mode, name, id) entries of a tree, each "<mode> <name>\0<20 byte id>"
+fn parse_tree(data: &[u8]) -> Option<Vec<(&str, String, Oid)>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let nul = rest.iter().position(|&b| b == 0)?;
+        let mode = std::str::from_utf8(&rest[..space]).ok()?;
+        let name = String::from_utf8_lossy(rest.get(space + 1..nul)?).into_owned();
+        let id = Oid::from_bytes(&rest[nul + 1..])?;
+        entries.push((mode, name, id));
+        rest = &rest[nul + 21..];
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas() {
+        let base = b"the quick brown fox";
+        // sizes 19 and 21, copy "the quick " (offset 0, size 10), insert "red", copy "fox"
+        let delta = [
+            19, 21, 0x90, 10, 3, b'r', b'e', b'd', 0x91, 16, 3, 5, b' ', b'j', b'u', b'm', b'p',
+        ];
+        assert_eq!(
+            b"the quick redfox jump".to_vec(),
+            apply_delta(base, &delta).unwrap()
+        );
+        // the base size has to agree
+        assert!(apply_delta(b"short", &delta).is_err());
+        // copying past the end of the base
+        assert!(apply_delta(base, &[19, 30, 0x91, 10, 30]).is_err());
+    }
+
+    #[test]
+    fn broken_packs() {
+        let id = Oid([1; 20]);
+        // an index of just `id`, at offset 0
+        let mut index = vec![0; 8];
+        index.extend_from_slice(&[0; 4]);
+        for _ in 1..256 {
+            index.extend_from_slice(&1u32.to_be_bytes());
+        }
+        index.extend_from_slice(&id.0);
+        index.extend_from_slice(&[0; 8]);
+        // an empty zlib stream, the delta of each entry
+        let empty = [
+            0x78, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+        ];
+
+        // a delta whose base is itself
+        let mut data = vec![0x70];
+        data.extend_from_slice(&id.0);
+        data.extend_from_slice(&empty);
+        // and one whose base is further back than any pack can be
+        let far = data.len();
+        data.push(0x60);
+        data.extend_from_slice(&[0xff; 12]);
+        data.push(0x7f);
+        data.extend_from_slice(&empty);
+
+        let repo = Repo {
+            git_dir: PathBuf::from("/no/such/minigrep/repo"),
+            packs: vec![Pack {
+                name: PathBuf::from("test.pack"),
+                index,
+                data,
+            }],
+            shallow: HashSet::new(),
+        };
+        let err = repo.read(id).unwrap_err();
+        assert!(err.to_string().ends_with("delta chain too long"), "{}", err);
+        let err = repo.read_packed(0, far).unwrap_err();
+        assert!(err.to_string().ends_with("outside the pack"), "{}", err);
+    }
+
+    #[test]
+    fn commits_and_trees() {
+        let commit = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+parent 0123456789abcdef0123456789abcdef01234567\n\
+author A U Thor <a@example.com> 1700000000 +0000\n\
+committer C O Mitter <c@example.com> 1700000100 +0100\n\
+\n\
+tree in the message\n";
+        let commit = parse_commit(commit).unwrap();
+        assert_eq!(
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+            commit.tree.to_string()
+        );
+        assert_eq!(1, commit.parents.len());
+        assert_eq!(1_700_000_100, commit.time);
+
+        let mut tree = b"100644 a.txt\0".to_vec();
+        tree.extend_from_slice(&[1; 20]);
+        tree.extend_from_slice(b"40000 src\0");
+        tree.extend_from_slice(&[2; 20]);
+        let entries = parse_tree(&tree).unwrap();
+        assert_eq!(("100644", String::from("a.txt"), Oid([1; 20])), entries[0]);
+        assert_eq!(("40000", String::from("src"), Oid([2; 20])), entries[1]);
+        assert!(parse_tree(&tree[..tree.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! shell style globs for picking which files to search
+//!
+//! `*` and `?` stay inside one path component, `**` crosses directories,
+//! `[a-z]` / `[!a-z]` are classes and `{rs,toml}` are alternatives.
+//! a glob without a `/` is matched against the file name only, like in `.gitignore`
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::regex::Regex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobError {
+    pub glob: String,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid glob '{}': {}", self.glob, self.msg)
+    }
+}
+
+impl Error for GlobError {}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    re: Regex,
+    // no '/' in the glob, so only the last path component is compared
+    basename: bool,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Glob, GlobError> {
+        let error = |msg| GlobError {
+            glob: glob.to_string(),
+            msg,
+        };
+        let pattern = translate(glob).map_err(error)?;
+        let re = Regex::new(&pattern, false).map_err(|_| error("unsupported pattern"))?;
+
+        Ok(Glob {
+            re,
+            basename: !glob.contains('/'),
+        })
+    }
+
+    /// match `path`, written relative to the directory being searched
+    pub fn is_match(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        let path = path.trim_start_matches("./");
+        if self.basename {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            self.re.is_match(name)
+        } else {
+            self.re.is_match(path)
+        }
+    }
+}
+
+// rewrite the glob as an anchored regular expression
+fn translate(glob: &str) -> Result<String, &'static str> {
+    let chars: Vec<char> = glob.trim_start_matches('/').chars().collect();
+    let mut out = String::from("^");
+    let mut braces = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                if at_start && chars.get(i + 2) == Some(&'/') {
+                    // `**/` is zero or more whole directories
+                    out.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    out.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .skip(1)
+                    .position(|&c| c == ']')
+                    .map(|end| i + 2 + end)
+                    .ok_or("missing ']'")?;
+                out.push('[');
+                let mut body = &chars[i + 1..end];
+                if let Some('!') | Some('^') = body.first() {
+                    out.push('^');
+                    body = &body[1..];
+                }
+                for &c in body {
+                    if c == '\\' || c == '[' {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push(']');
+                i = end;
+            }
+            '{' => {
+                braces += 1;
+                out.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                out.push(')');
+            }
+            ',' if braces > 0 => out.push('|'),
+            '\\' => {
+                i += 1;
+                let c = *chars.get(i).ok_or("trailing '\\'")?;
+                push_literal(&mut out, c);
+            }
+            c => push_literal(&mut out, c),
+        }
+        i += 1;
+    }
+
+    if braces > 0 {
+        return Err("missing '}'");
+    }
+    out.push('$');
+    Ok(out)
+}
+
+fn push_literal(out: &mut String, c: char) {
+    if "\\.+*?()|[]{}^$".contains(c) {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(Path::new(path))
+    }
+
+    #[test]
+    fn star_stays_in_one_component() {
+        assert!(matches("*.rs", "src/lib.rs"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(!matches("*.rs", "lib.rs.orig"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(matches("tests/**", "tests/data/values.txt.gz"));
+        assert!(matches("**/data/*.gz", "tests/data/values.txt.gz"));
+        assert!(matches("**/data/*.gz", "data/values.txt.gz"));
+        assert!(!matches("tests/**", "src/tests.rs"));
+    }
+
+    #[test]
+    fn classes_and_alternatives() {
+        assert!(matches("[0-9]*_*.rs", "19_smart_pointers.rs"));
+        assert!(!matches("[!0-9]*.rs", "19_smart_pointers.rs"));
+        assert!(matches("*.{md,markdown}", "README.md"));
+        assert!(matches("Cargo.{toml,lock}", "Cargo.lock"));
+        assert!(matches("file?.txt", "file1.txt"));
+    }
+
+    #[test]
+    fn bad_globs() {
+        assert_eq!("missing '}'", Glob::new("*.{rs").unwrap_err().msg);
+        assert_eq!("missing ']'", Glob::new("[a-").unwrap_err().msg);
+    }
+}
@@ -0,0 +1,312 @@
+//! `--html`: write the results as one static page instead of printing them
+//!
+//! matches are grouped by file with a few lines of context around them, every
+//! line has its own anchor and a summary table at the top links to each file.
+//! the styles are inline, so the page works on its own without any other files
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::regex::Regex;
+use crate::stats::Stats;
+
+/// lines shown before and after each match
+pub const CONTEXT: usize = 2;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table.summary { border-collapse: collapse; margin-bottom: 2em; }
+table.summary td, table.summary th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+table.summary td.n { text-align: right; }
+section { margin-bottom: 2em; }
+h2 { font-size: 1.1em; font-family: monospace; }
+table.code { border-collapse: collapse; font-family: monospace; width: 100%; }
+table.code td { padding: 0 0.5em; white-space: pre-wrap; vertical-align: top; }
+table.code td.num { text-align: right; color: #888; width: 4em; user-select: none; }
+table.code td.num a { color: inherit; text-decoration: none; }
+tr.match { background: #fffbe6; }
+tr.gap td { color: #aaa; }
+tr:target { background: #ffe58f; }
+mark { background: #ffd33d; }
+";
+
+// one line shown on the page
+struct Line {
+    text: String,
+    matched: bool,
+}
+
+struct FileReport {
+    path: String,
+    lines: BTreeMap<usize, Line>,
+}
+
+/// collects the files of one search, then writes the page
+#[derive(Default)]
+pub struct Report {
+    files: Vec<FileReport>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    /// `results` are matching lines (or spans of lines with `--multiline`) with the
+    /// number of their first line, `context` any lines around them
+    pub fn add(&mut self, path: &Path, results: &[(usize, String)], context: &[(usize, String)]) {
+        if results.is_empty() {
+            return;
+        }
+        let mut lines = BTreeMap::new();
+        for (number, text) in results {
+            for (offset, line) in text.lines().enumerate() {
+                lines.insert(
+                    number + offset,
+                    Line {
+                        text: line.to_string(),
+                        matched: true,
+                    },
+                );
+            }
+        }
+        // only context that belongs to a result that is kept, results may
+        // have been dropped by `--max-total`
+        let near = |n: usize| {
+            lines
+                .range(n.saturating_sub(CONTEXT)..=n + CONTEXT)
+                .any(|(_, line)| line.matched)
+        };
+        let context: Vec<(usize, Line)> = context
+            .iter()
+            .filter(|(n, _)| !lines.contains_key(n) && near(*n))
+            .map(|(n, text)| {
+                let line = Line {
+                    text: text.clone(),
+                    matched: false,
+                };
+                (*n, line)
+            })
+            .collect();
+        lines.extend(context);
+
+        self.files.push(FileReport {
+            path: path.display().to_string(),
+            lines,
+        });
+    }
+
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        query: &str,
+        pattern: &Regex,
+        stats: &Stats,
+    ) -> io::Result<()> {
+        let mut page = String::new();
+        // writing into a String can't fail
+        self.render(&mut page, query, pattern, stats).unwrap();
+        out.write_all(page.as_bytes())
+    }
+
+    fn render(
+        &self,
+        page: &mut String,
+        query: &str,
+        pattern: &Regex,
+        stats: &Stats,
+    ) -> std::fmt::Result {
+        let title = format!("minigrep: {}", escape(query));
+        writeln!(
+            page,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        )?;
+        writeln!(page, "<title>{}</title>\n<style>\n{}</style>", title, STYLE)?;
+        writeln!(page, "</head>\n<body>\n<h1>{}</h1>", title)?;
+        writeln!(
+            page,
+            "<p>{} matching lines in {} of {} files searched.</p>",
+            stats.matched_lines, stats.files_matched, stats.files_scanned
+        )?;
+
+        writeln!(page, "<table class=\"summary\">")?;
+        writeln!(page, "<tr><th>file</th><th>matching lines</th></tr>")?;
+        for (i, file) in self.files.iter().enumerate() {
+            let count = file.lines.values().filter(|line| line.matched).count();
+            writeln!(
+                page,
+                "<tr><td><a href=\"#f{}\">{}</a></td><td class=\"n\">{}</td></tr>",
+                i,
+                escape(&file.path),
+                count
+            )?;
+        }
+        writeln!(page, "</table>")?;
+
+        for (i, file) in self.files.iter().enumerate() {
+            writeln!(
+                page,
+                "<section id=\"f{}\">\n<h2>{}</h2>\n<table class=\"code\">",
+                i,
+                escape(&file.path)
+            )?;
+            let mut previous = None;
+            for (number, line) in &file.lines {
+                // a break in the numbering is shown as a gap
+                if previous.is_some_and(|p| p + 1 < *number) {
+                    writeln!(
+                        page,
+                        "<tr class=\"gap\"><td class=\"num\">…</td><td></td></tr>"
+                    )?;
+                }
+                previous = Some(*number);
+
+                let anchor = format!("f{}-L{}", i, number);
+                let text = if line.matched {
+                    highlight(pattern, &line.text)
+                } else {
+                    escape(&line.text)
+                };
+                writeln!(
+                    page,
+                    "<tr id=\"{0}\" class=\"{1}\"><td class=\"num\"><a href=\"#{0}\">{2}</a></td><td>{3}</td></tr>",
+                    anchor,
+                    if line.matched { "match" } else { "context" },
+                    number,
+                    text
+                )?;
+            }
+            writeln!(page, "</table>\n</section>")?;
+        }
+
+        writeln!(page, "</body>\n</html>")
+    }
+}
+
+/// the lines within `CONTEXT` of any result, leaving out the results themselves
+pub fn context(contents: &str, results: &[(usize, &str)]) -> Vec<(usize, String)> {
+    // (first, last) line of each result
+    let spans: Vec<(usize, usize)> = results
+        .iter()
+        .map(|(number, text)| (*number, number + text.lines().count().max(1) - 1))
+        .collect();
+    let last = match spans.last() {
+        Some(&(_, last)) => last + CONTEXT,
+        None => return Vec::new(),
+    };
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .take_while(|(n, _)| *n <= last)
+        .filter(|&(n, _)| {
+            // the spans are sorted, so look up the first one that ends near or after `n`
+            let i = spans.partition_point(|&(_, last)| last + CONTEXT < n);
+            let near = spans
+                .get(i)
+                .is_some_and(|&(first, _)| first.saturating_sub(CONTEXT) <= n);
+            let i = spans.partition_point(|&(_, last)| last < n);
+            let inside = spans.get(i).is_some_and(|&(first, _)| first <= n);
+            near && !inside
+        })
+        .map(|(n, line)| (n, line.to_string()))
+        .collect()
+}
+
+// wrap every match in <mark>
+fn highlight(pattern: &Regex, line: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for (start, end) in pattern.find_iter(line) {
+        if end == start {
+            continue;
+        }
+        out.push_str(&escape(&line[last..start]));
+        out.push_str("<mark>");
+        out.push_str(&escape(&line[start..end]));
+        out.push_str("</mark>");
+        last = end;
+    }
+    out.push_str(&escape(&line[last..]));
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+    #[test]
+    fn context_lines() {
+        let results = vec![(1, "I'm nobody! Who are you?"), (8, "To tell your name")];
+        let numbers: Vec<usize> = context(POEM, &results).iter().map(|(n, _)| *n).collect();
+
+        assert_eq!(vec![2, 3, 6, 7, 9], numbers);
+    }
+
+    #[test]
+    fn highlights_and_escapes() {
+        let pattern = Regex::literal("<b>", false);
+        assert_eq!(
+            "a <mark>&lt;b&gt;</mark> &amp; <mark>&lt;b&gt;</mark>",
+            highlight(&pattern, "a <b> & <b>")
+        );
+    }
+
+    #[test]
+    fn page() {
+        let pattern = Regex::literal("frog", false);
+        let results = vec![(7, String::from("How public, like a frog"))];
+        let found: Vec<(usize, &str)> = results.iter().map(|(n, t)| (*n, t.as_str())).collect();
+        let mut report = Report::new();
+        report.add(Path::new("poem.txt"), &results, &context(POEM, &found));
+
+        let stats = Stats {
+            files_scanned: 2,
+            files_matched: 1,
+            matched_lines: 1,
+            ..Stats::default()
+        };
+        let mut page = Vec::new();
+        report.write(&mut page, "frog", &pattern, &stats).unwrap();
+        let page = String::from_utf8(page).unwrap();
+
+        assert!(page.contains("<a href=\"#f0\">poem.txt</a></td><td class=\"n\">1</td>"));
+        assert!(page.contains(
+            "<tr id=\"f0-L7\" class=\"match\"><td class=\"num\"><a href=\"#f0-L7\">7</a></td>\
+             <td>How public, like a <mark>frog</mark></td></tr>"
+        ));
+        assert!(page.contains("id=\"f0-L5\" class=\"context\""));
+        assert!(page.contains("id=\"f0-L9\" class=\"context\""));
+        // nothing is loaded from anywhere else
+        assert!(!page.contains("src=") && !page.contains("<link"));
+    }
+}
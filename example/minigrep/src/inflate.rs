@@ -0,0 +1,567 @@
+//! a small DEFLATE decoder (RFC 1951) with gzip (RFC 1952) and zlib (RFC 1950) framing
+//!
+//! rotated logs are usually `.gz`, so minigrep inflates them itself instead of
+//! shelling out to `gzip -d`
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InflateError(&'static str);
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "decompression failed: {}", self.0)
+    }
+}
+
+impl Error for InflateError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+}
+
+/// guess the container format from the first bytes of `data`
+pub fn detect(data: &[u8]) -> Option<Format> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::Gzip)
+    } else if data.len() >= 2 && is_zlib_header(data[0], data[1]) {
+        Some(Format::Zlib)
+    } else {
+        None
+    }
+}
+
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    // deflate method, window <= 32K, no preset dictionary, valid check bits
+    cmf & 0x0f == 8
+        && cmf >> 4 <= 7
+        && flg & 0x20 == 0
+        && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+}
+
+/// decompress `data` when it carries a gzip or zlib header, otherwise hand it back untouched
+///
+/// with `force` set, data without a known header is decoded as a raw deflate stream.
+/// a zlib header is only two bytes and plain text can start with one by accident,
+/// so in automatic mode a failed zlib decode falls back to the original bytes
+pub fn decompress(data: Vec<u8>, force: bool) -> Result<Vec<u8>, InflateError> {
+    match detect(&data) {
+        Some(Format::Gzip) => gunzip(&data),
+        Some(Format::Zlib) if force => zlib_decompress(&data),
+        Some(Format::Zlib) => Ok(zlib_decompress(&data).unwrap_or(data)),
+        None if force => inflate(&data).map(|(out, _)| out),
+        None => Ok(data),
+    }
+}
+
+/// decode every member of a gzip file, concatenated
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, InflateError> {
+    let mut out = Vec::new();
+    let mut rest = data;
+
+    // `cat a.gz b.gz > c.gz` is valid gzip, keep going while another member follows
+    while rest.starts_with(&[0x1f, 0x8b]) {
+        let header = gzip_header_len(rest)?;
+        let (member, used) = inflate(&rest[header..])?;
+        let trailer = rest
+            .get(header + used..header + used + 8)
+            .ok_or(InflateError("truncated gzip trailer"))?;
+
+        if read_le32(&trailer[..4]) != crc32(&member) {
+            return Err(InflateError("gzip crc mismatch"));
+        }
+        if read_le32(&trailer[4..]) != member.len() as u32 {
+            return Err(InflateError("gzip length mismatch"));
+        }
+
+        out.extend_from_slice(&member);
+        rest = &rest[header + used + 8..];
+    }
+
+    if out.is_empty() && rest.len() == data.len() {
+        return Err(InflateError("not a gzip stream"));
+    }
+    Ok(out)
+}
+
+fn gzip_header_len(data: &[u8]) -> Result<usize, InflateError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let truncated = InflateError("truncated gzip header");
+    if data.len() < 10 {
+        return Err(truncated);
+    }
+    if data[2] != 8 {
+        return Err(InflateError("unknown gzip compression method"));
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let xlen = data.get(pos..pos + 2).ok_or_else(|| truncated.clone())?;
+        pos += 2 + (usize::from(xlen[0]) | usize::from(xlen[1]) << 8);
+    }
+    // file name and comment are zero terminated
+    for flag in &[FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let rest = data.get(pos..).ok_or_else(|| truncated.clone())?;
+            let end = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| truncated.clone())?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    if pos > data.len() {
+        return Err(truncated);
+    }
+    Ok(pos)
+}
+
+/// decode a zlib stream and check its adler32 trailer
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 2 || !is_zlib_header(data[0], data[1]) {
+        return Err(InflateError("not a zlib stream"));
+    }
+
+    let (out, used) = inflate(&data[2..])?;
+    let trailer = data
+        .get(2 + used..2 + used + 4)
+        .ok_or(InflateError("truncated zlib trailer"))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if expected != adler32(&out) {
+        return Err(InflateError("zlib checksum mismatch"));
+    }
+
+    Ok(out)
+}
+
+/// decode a raw deflate stream, returning the output and the number of input bytes used
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut input = BitReader::new(data);
+    // `data` may go on past the stream, as in a packfile, so only guess so far
+    let mut out = Vec::with_capacity(data.len().saturating_mul(4).min(1 << 20));
+
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored(&mut input, &mut out)?,
+            1 => {
+                let (lencode, distcode) = fixed_tables();
+                codes(&mut input, &mut out, &lencode, &distcode)?;
+            }
+            2 => {
+                let (lencode, distcode) = dynamic_tables(&mut input)?;
+                codes(&mut input, &mut out, &lencode, &distcode)?;
+            }
+            _ => return Err(InflateError("invalid block type")),
+        }
+        if last {
+            break;
+        }
+    }
+
+    Ok((out, input.pos))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bitbuf: u32,
+    bitcnt: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bitbuf: 0,
+            bitcnt: 0,
+        }
+    }
+
+    // deflate packs bits starting from the least significant one
+    fn bits(&mut self, need: u32) -> Result<u32, InflateError> {
+        let mut val = self.bitbuf;
+        while self.bitcnt < need {
+            let byte = self.next_byte()?;
+            val |= u32::from(byte) << self.bitcnt;
+            self.bitcnt += 8;
+        }
+        self.bitbuf = val >> need;
+        self.bitcnt -= need;
+        Ok(val & ((1 << need) - 1))
+    }
+
+    fn next_byte(&mut self) -> Result<u8, InflateError> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or(InflateError("unexpected end of compressed data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    // drop what is left of the current byte
+    fn align(&mut self) {
+        self.bitbuf = 0;
+        self.bitcnt = 0;
+    }
+}
+
+fn stored(input: &mut BitReader, out: &mut Vec<u8>) -> Result<(), InflateError> {
+    input.align();
+    let mut header = [0u8; 4];
+    for byte in header.iter_mut() {
+        *byte = input.next_byte()?;
+    }
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(InflateError("stored block length mismatch"));
+    }
+
+    let end = input.pos + usize::from(len);
+    let block = input
+        .data
+        .get(input.pos..end)
+        .ok_or(InflateError("unexpected end of compressed data"))?;
+    out.extend_from_slice(block);
+    input.pos = end;
+    Ok(())
+}
+
+const MAX_BITS: usize = 15;
+
+// canonical huffman code stored as a count per length plus symbols sorted by code
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[usize::from(len)] += 1;
+        }
+
+        // a code may be incomplete, but never over-subscribed
+        let mut left: i32 = 1;
+        for &n in &count[1..] {
+            left <<= 1;
+            left -= i32::from(n);
+            if left < 0 {
+                return Err(InflateError("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offs = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offs[len + 1] = offs[len] + count[len];
+        }
+        let mut symbol = vec![0u16; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[usize::from(offs[usize::from(len)])] = sym as u16;
+                offs[usize::from(len)] += 1;
+            }
+        }
+
+        Ok(Huffman { count, symbol })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, InflateError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for len in 1..=MAX_BITS {
+            code |= input.bits(1)? as i32;
+            let count = i32::from(self.count[len]);
+            if code - count < first {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(InflateError("invalid huffman code"))
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (sym, len) in lengths.iter_mut().enumerate() {
+        *len = match sym {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    let lencode = Huffman::new(&lengths).expect("fixed literal code is valid");
+    let distcode = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+    (lencode, distcode)
+}
+
+fn dynamic_tables(input: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let nlen = input.bits(5)? as usize + 257;
+    let ndist = input.bits(5)? as usize + 1;
+    let ncode = input.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(InflateError("bad dynamic block counts"));
+    }
+
+    let mut lengths = [0u8; 19];
+    for &index in &ORDER[..ncode] {
+        lengths[index] = input.bits(3)? as u8;
+    }
+    let lencode = Huffman::new(&lengths)?;
+
+    // literal/length and distance code lengths share one run-length encoded list
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut index = 0;
+    while index < nlen + ndist {
+        let symbol = lencode.decode(input)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+
+        let (value, repeat) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(InflateError("repeat with no previous length"));
+                }
+                (lengths[index - 1], 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+        if index + repeat > nlen + ndist {
+            return Err(InflateError("too many code lengths"));
+        }
+        for len in &mut lengths[index..index + repeat] {
+            *len = value;
+        }
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(InflateError("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..])?,
+    ))
+}
+
+fn codes(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    lencode: &Huffman,
+    distcode: &Huffman,
+) -> Result<(), InflateError> {
+    const LBASE: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LEXT: [u32; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DBASE: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DEXT: [u32; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+
+    loop {
+        let symbol = usize::from(lencode.decode(input)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LBASE.len() {
+            return Err(InflateError("invalid length symbol"));
+        }
+        let len = usize::from(LBASE[symbol]) + input.bits(LEXT[symbol])? as usize;
+
+        let symbol = usize::from(distcode.decode(input)?);
+        if symbol >= DBASE.len() {
+            return Err(InflateError("invalid distance symbol"));
+        }
+        let dist = usize::from(DBASE[symbol]) + input.bits(DEXT[symbol])? as usize;
+        if dist > out.len() {
+            return Err(InflateError("distance too far back"));
+        }
+
+        // the copy may overlap what it is producing, so go byte by byte
+        let start = out.len() - dist;
+        for i in 0..len {
+            let byte = out[start + i];
+            out.push(byte);
+        }
+    }
+}
+
+fn read_le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` may overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+";
+
+    // printf '...' | gzip -n
+    const GZIP: [u8; 69] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x0b, 0x2a, 0x2d, 0x2e, 0xb1,
+        0xe2, 0x2a, 0x4e, 0x4c, 0x4b, 0xd5, 0x51, 0x48, 0x4b, 0x2c, 0x2e, 0xd1, 0x51, 0x28, 0x28,
+        0xca, 0x4f, 0x29, 0x4d, 0x2e, 0xc9, 0x2c, 0x4b, 0xd5, 0xe3, 0x0a, 0xc8, 0x4c, 0xce, 0x56,
+        0x28, 0xc9, 0x28, 0x4a, 0x05, 0xb2, 0x5d, 0x80, 0x82, 0x0a, 0x25, 0x89, 0x05, 0x40, 0x26,
+        0x00, 0x19, 0x83, 0x21, 0xb6, 0x35, 0x00, 0x00, 0x00,
+    ];
+
+    // the same deflate data with a zlib header and adler32 trailer
+    const ZLIB: [u8; 57] = [
+        0x78, 0x9c, 0x0b, 0x2a, 0x2d, 0x2e, 0xb1, 0xe2, 0x2a, 0x4e, 0x4c, 0x4b, 0xd5, 0x51, 0x48,
+        0x4b, 0x2c, 0x2e, 0xd1, 0x51, 0x28, 0x28, 0xca, 0x4f, 0x29, 0x4d, 0x2e, 0xc9, 0x2c, 0x4b,
+        0xd5, 0xe3, 0x0a, 0xc8, 0x4c, 0xce, 0x56, 0x28, 0xc9, 0x28, 0x4a, 0x05, 0xb2, 0x5d, 0x80,
+        0x82, 0x0a, 0x25, 0x89, 0x05, 0x40, 0x26, 0x00, 0xed, 0xd2, 0x11, 0xde,
+    ];
+
+    #[test]
+    fn gzip_fixed_block() {
+        assert_eq!(Some(Format::Gzip), detect(&GZIP));
+        assert_eq!(TEXT.as_bytes(), &gunzip(&GZIP).unwrap()[..]);
+    }
+
+    #[test]
+    fn gzip_dynamic_block() {
+        let data = include_bytes!("../tests/data/values.txt.gz");
+        let expected: String = (0..200)
+            .map(|i| format!("line {}: value {}\n", i, i * i % 97))
+            .collect();
+
+        assert_eq!(expected.as_bytes(), &gunzip(data).unwrap()[..]);
+    }
+
+    #[test]
+    fn gzip_members_are_concatenated() {
+        let mut data = GZIP.to_vec();
+        data.extend_from_slice(&GZIP);
+
+        assert_eq!(TEXT.repeat(2).as_bytes(), &gunzip(&data).unwrap()[..]);
+    }
+
+    #[test]
+    fn zlib_stream() {
+        assert_eq!(Some(Format::Zlib), detect(&ZLIB));
+        assert_eq!(TEXT.as_bytes(), &zlib_decompress(&ZLIB).unwrap()[..]);
+    }
+
+    #[test]
+    fn raw_deflate_needs_force() {
+        let raw = ZLIB[2..ZLIB.len() - 4].to_vec();
+
+        assert_eq!(raw, decompress(raw.clone(), false).unwrap());
+        assert_eq!(TEXT.as_bytes(), &decompress(raw, true).unwrap()[..]);
+    }
+
+    #[test]
+    fn plain_text_passes_through() {
+        // "x^" happens to be a valid zlib header
+        let text = b"x^2 + y^2".to_vec();
+
+        assert_eq!(Some(Format::Zlib), detect(&text));
+        assert_eq!(text, decompress(text.clone(), false).unwrap());
+    }
+
+    #[test]
+    fn corrupt_data_is_rejected() {
+        let mut data = GZIP.to_vec();
+        let len = data.len();
+        data[len - 8] ^= 0xff;
+
+        assert_eq!(Err(InflateError("gzip crc mismatch")), gunzip(&data));
+        assert!(gunzip(&GZIP[..30]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! `--json-path`: match one value out of each line of JSON Lines
+//!
+//! every line is parsed with `Json::parse` and the path picks a value out of it:
+//! `.request.status`, `.tags[0]`, `.["key with.dots"]`, or `.` for the whole
+//! line. a query starting with `<`, `<=`, `>`, `>=`, `==` or `!=` compares the
+//! value as a number (a number, or a string holding one), any other query is
+//! matched against it as text: a string as it is, anything else as compact JSON.
+//! lines that aren't valid JSON are counted instead of stopping the search
+
+use std::fmt;
+
+use crate::cancel::Cancel;
+use crate::json::Json;
+use crate::regex::Regex;
+
+#[derive(Debug, PartialEq)]
+pub struct PathError {
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid JSON path at position {}: {}",
+            self.pos, self.msg
+        )
+    }
+}
+
+impl std::error::Error for PathError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(text: &str) -> Result<JsonPath, PathError> {
+        let chars: Vec<char> = text.chars().collect();
+        let error = |pos, msg| Err(PathError { pos, msg });
+        if chars.first() != Some(&'.') {
+            return error(0, "a path starts with '.'");
+        }
+
+        let mut steps = Vec::new();
+        let mut pos = 0;
+        // `.` on its own is the whole value
+        if chars.len() == 1 {
+            return Ok(JsonPath { steps });
+        }
+        while pos < chars.len() {
+            match chars[pos] {
+                '.' if chars.get(pos + 1) == Some(&'[') => pos += 1,
+                '.' => {
+                    let start = pos + 1;
+                    pos = start;
+                    while pos < chars.len() && chars[pos] != '.' && chars[pos] != '[' {
+                        pos += 1;
+                    }
+                    if pos == start {
+                        return error(start, "expected a key after '.'");
+                    }
+                    steps.push(Step::Key(chars[start..pos].iter().collect()));
+                }
+                '[' if chars.get(pos + 1) == Some(&'"') => {
+                    let start = pos + 2;
+                    let end = match chars[start..].iter().position(|&c| c == '"') {
+                        Some(len) => start + len,
+                        None => return error(pos, "unclosed '\"'"),
+                    };
+                    if chars.get(end + 1) != Some(&']') {
+                        return error(end + 1, "expected ']'");
+                    }
+                    steps.push(Step::Key(chars[start..end].iter().collect()));
+                    pos = end + 2;
+                }
+                '[' => {
+                    let start = pos + 1;
+                    let end = match chars[start..].iter().position(|&c| c == ']') {
+                        Some(len) => start + len,
+                        None => return error(pos, "unclosed '['"),
+                    };
+                    let index: String = chars[start..end].iter().collect();
+                    match index.parse() {
+                        Ok(index) => steps.push(Step::Index(index)),
+                        Err(_) => return error(start, "expected an index or a quoted key"),
+                    }
+                    pos = end + 1;
+                }
+                _ => return error(pos, "expected '.' or '['"),
+            }
+        }
+        Ok(JsonPath { steps })
+    }
+
+    pub fn select<'a>(&self, value: &'a Json) -> Option<&'a Json> {
+        self.steps
+            .iter()
+            .try_fold(value, |value, step| match (step, value) {
+                (Step::Key(key), _) => value.get(key),
+                (Step::Index(i), Json::Array(items)) => items.get(*i),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+/// the comparison a query asks for, `None` when it's plain text to match
+pub fn comparison(query: &str) -> Option<(Compare, f64)> {
+    let query = query.trim();
+    // two-character operators first, so `<=` isn't read as `<`
+    let operators = [
+        ("<=", Compare::LessEqual),
+        (">=", Compare::GreaterEqual),
+        ("==", Compare::Equal),
+        ("!=", Compare::NotEqual),
+        ("<", Compare::Less),
+        (">", Compare::Greater),
+    ];
+    operators.iter().find_map(|(op, compare)| {
+        let number = query.strip_prefix(op)?.trim().parse().ok()?;
+        Some((*compare, number))
+    })
+}
+
+impl Compare {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Compare::Less => left < right,
+            Compare::LessEqual => left <= right,
+            Compare::Greater => left > right,
+            Compare::GreaterEqual => left >= right,
+            Compare::Equal => left == right,
+            Compare::NotEqual => left != right,
+        }
+    }
+}
+
+// the matching lines, the matches in the value of each, and how many lines
+// weren't valid JSON
+type Matched = (Vec<(usize, String)>, Vec<usize>, usize);
+
+/// the lines whose value at `path` matches `pattern`, or passes the comparison
+/// in `query`. a comparison that holds is one match
+pub fn search(
+    path: &JsonPath,
+    query: &str,
+    pattern: &Regex,
+    text: &str,
+    max: usize,
+    cancel: &Cancel,
+) -> Matched {
+    let comparison = comparison(query);
+    let mut invalid = 0;
+    let mut results = Vec::new();
+    let mut counts = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        if results.len() == max || cancel.is_cancelled() {
+            break;
+        }
+        // blank lines between records are allowed
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = match Json::parse(line) {
+            Ok(value) => value,
+            Err(_) => {
+                invalid += 1;
+                continue;
+            }
+        };
+        let count = match (path.select(&value), comparison) {
+            (None, _) => 0,
+            (Some(found), Some((compare, number))) => {
+                let found = match found {
+                    Json::String(s) => s.trim().parse().ok(),
+                    other => other.as_f64(),
+                };
+                usize::from(found.is_some_and(|found| compare.holds(found, number)))
+            }
+            (Some(Json::String(s)), None) => pattern.find_iter(s).count(),
+            (Some(found), None) => pattern.find_iter(&found.to_string()).count(),
+        };
+        if count > 0 {
+            results.push((index + 1, line.to_string()));
+            counts.push(count);
+        }
+    }
+    (results, counts, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"{"request": {"status": 200, "path": "/404.html"}, "tags": ["a"]}
+{"request": {"status": 404, "path": "/missing"}, "tags": []}
+not json
+{"request": {"status": "503", "path": "/admin"}}
+{"request": {"status": 500,
+"#;
+
+    fn lines(path: &str, query: &str) -> (Vec<usize>, usize) {
+        let path = JsonPath::parse(path).unwrap();
+        let pattern = Regex::literal(query, false);
+        let (results, _, invalid) = search(&path, query, &pattern, LOG, usize::MAX, &Cancel::new());
+        (results.into_iter().map(|(n, _)| n).collect(), invalid)
+    }
+
+    fn counts(path: &str, query: &str) -> Vec<usize> {
+        let path = JsonPath::parse(path).unwrap();
+        let pattern = Regex::literal(query, false);
+        search(&path, query, &pattern, LOG, usize::MAX, &Cancel::new()).1
+    }
+
+    #[test]
+    fn paths() {
+        let value = Json::parse(r#"{"a": {"b.c": [1, {"d": true}]}}"#).unwrap();
+        let select = |path| JsonPath::parse(path).unwrap().select(&value).cloned();
+        assert_eq!(Some(Json::Bool(true)), select(r#".a["b.c"][1].d"#));
+        assert_eq!(Some(value.clone()), select("."));
+        assert_eq!(None, select(".a.b"));
+
+        assert_eq!(0, JsonPath::parse("a").unwrap_err().pos);
+        assert_eq!(3, JsonPath::parse(".a..b").unwrap_err().pos);
+        assert_eq!(3, JsonPath::parse(".a[x]").unwrap_err().pos);
+    }
+
+    #[test]
+    fn text_and_numbers() {
+        // only the status counts, not the path that mentions 404
+        assert_eq!((vec![2], 2), lines(".request.status", "404"));
+        assert_eq!((vec![2, 4], 2), lines(".request.status", ">= 400"));
+        assert_eq!((vec![1, 2], 2), lines(".request.status", "< 500"));
+
+        // matches are only counted in the value, a comparison is one
+        assert_eq!(vec![2], counts(".request.path", "s"));
+        assert_eq!(vec![1, 1], counts(".request.status", ">= 400"));
+        assert_eq!((vec![1], 2), lines(".request.path", "404"));
+        assert_eq!((vec![1], 2), lines(".tags[0]", "a"));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(Some((Compare::LessEqual, 1.5)), comparison("<=1.5"));
+        assert_eq!(Some((Compare::NotEqual, 200.0)), comparison("!= 200"));
+        assert_eq!(None, comparison("<html>"));
+        assert_eq!(None, comparison("500"));
+    }
+}
@@ -1,50 +1,1311 @@
+use std::collections::HashMap;
 use std::env;
 // --snip--
 
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod aggregate;
+pub mod budget;
+pub mod cache;
+pub mod cancel;
+pub mod csv;
+pub mod diff;
+pub mod ffi;
+pub mod follow;
+pub mod format;
+pub mod git;
+pub mod glob;
+pub mod html;
+pub mod inflate;
+pub mod json;
+pub mod jsonpath;
+pub mod mmap;
+pub mod plugin;
+pub mod query;
+pub mod regex;
+pub mod rpc;
+pub mod scope;
+pub mod server;
+pub mod sort;
+pub mod stats;
+pub mod timestamp;
+pub mod types;
+pub mod walk;
+
+use aggregate::{Counter, GroupBy, Table};
+use budget::{Budget, Messenger};
+use cache::{Cache, Stamp};
+use cancel::Cancel;
+use format::Format;
+use html::Report;
+use jsonpath::JsonPath;
+use plugin::Matcher;
+use query::Plan;
+use regex::{Regex, RegexError};
+use scope::Scope;
+use sort::{Sort, SortBy};
+use stats::Stats;
+use timestamp::Window;
+use types::Types;
+use walk::Filter;
 
 // use config to sum config data
 pub struct Config {
     pub query: String,
-    pub filename: String,
+    // files or directories to search, directories are searched recursively
+    pub paths: Vec<String>,
     pub case_sensitive: bool,
+    // print the line number before each match
+    pub line_number: bool,
+    // treat the input as compressed even without a gzip/zlib header
+    pub decompress: bool,
+    // the query is a regular expression instead of a plain string
+    pub regex: bool,
+    // match against the whole file so a match can span several lines
+    pub multiline: bool,
+    // print a summary once the search is done
+    pub stats: bool,
+    // keep quiet about files that can't be read
+    pub no_messages: bool,
+    // `--glob` patterns, a leading `!` excludes
+    pub globs: Vec<String>,
+    // file types to search (`-t`) and to skip (`-T`)
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    // extra `name:glob` type definitions
+    pub type_add: Vec<String>,
+    // stop after this many matching lines in each file
+    pub max_count: Option<usize>,
+    // stop the whole search after this many matching lines
+    pub max_total: Option<usize>,
+    // give up and print what was found once this much time has passed
+    pub timeout: Option<Duration>,
+    // number of files searched at the same time
+    pub threads: usize,
+    // count matches by value instead of printing them
+    pub aggregate: Option<GroupBy>,
+    // rows in the `--aggregate` table
+    pub top: usize,
+    // in Rust files, only count matches in code, comments or string literals
+    pub scope: Option<Scope>,
+    // big files may be searched through a memory map
+    pub mmap: bool,
+    // print files in this order instead of whichever finishes first
+    pub sort: Option<Sort>,
+    // write the results to this HTML page instead of printing them
+    pub html: Option<PathBuf>,
+    // reuse results stored on disk for files that haven't changed
+    pub cache: bool,
+    pub cache_dir: Option<PathBuf>,
+    // bytes the cache directory may grow to
+    pub cache_size: u64,
+    // a matcher from a plugin decides which lines match instead of the query
+    pub matcher: Option<String>,
+    pub plugin_dir: Option<PathBuf>,
+    // search every commit reachable from HEAD in the repository the path is in
+    pub git_history: bool,
+    // warn when getting close to these, and stop the search once they are reached
+    pub budget_bytes: Option<u64>,
+    pub budget_matches: Option<usize>,
+    // `--csv` or `--tsv`: the input is records split by this byte
+    pub delimiter: Option<u8>,
+    // columns to match in, by header name or 1-based index
+    pub fields: Vec<String>,
+    // the input is JSON Lines, match the query against the value at this path
+    pub json_path: Option<JsonPath>,
+    // `--since` and `--until`: only lines with a timestamp inside this window
+    pub window: Option<Window>,
+    // keep reading lines as they are appended to the files
+    pub follow: bool,
+    // `--format`: a record with a line and column for every match
+    pub format: Option<Format>,
+    // `--bool`: the query compiled as a boolean expression
+    pub plan: Option<Plan>,
+    // `--bool-scope file`: the expression holds for a whole file instead of a line
+    pub file_scope: bool,
 }
 
 impl Config {
-    pub fn new(mut args: env::Args) -> Result<Config, &str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
+        // skip the program name
+        args.next();
+        // arguments from the config file go first, so the command line can add to them
+        let mut args = config_file_args()?.into_iter().chain(args);
+
+        let mut line_number = false;
+        let mut decompress = false;
+        let mut regex = false;
+        let mut multiline = false;
+        let mut stats = false;
+        let mut no_messages = false;
+        let mut globs = Vec::new();
+        let mut types = Vec::new();
+        let mut types_not = Vec::new();
+        let mut type_add = Vec::new();
+        let mut max_count = None;
+        let mut max_total = None;
+        let mut timeout = None;
+        let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut aggregate = None;
+        let mut top = 10;
+        let mut scope = None;
+        let mut mmap = true;
+        let mut sort = None;
+        let mut html = None;
+        let mut cache = false;
+        let mut cache_dir = None;
+        let mut cache_size = 64 << 20;
+        let mut matcher = None;
+        let mut plugin_dir = None;
+        let mut git_history = false;
+        let mut budget_bytes = None;
+        let mut budget_matches = None;
+        let mut delimiter = None;
+        let mut fields = Vec::new();
+        let mut json_path = None;
+        let mut since = None;
+        let mut until = None;
+        let mut time_format = None;
+        let mut sorted = false;
+        let mut follow = false;
+        let mut format = None;
+        let mut boolean = false;
+        let mut file_scope = None;
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-n" | "--line-number" => line_number = true,
+                "-z" | "--decompress" => decompress = true,
+                "-E" | "--regex" => regex = true,
+                "-U" | "--multiline" => multiline = true,
+                "--stats" => stats = true,
+                "-s" | "--no-messages" => no_messages = true,
+                "-g" | "--glob" => globs.push(value(&mut args, &arg)?),
+                "-t" | "--type" => types.push(value(&mut args, &arg)?),
+                "-T" | "--type-not" => types_not.push(value(&mut args, &arg)?),
+                "--type-add" => type_add.push(value(&mut args, &arg)?),
+                "-m" | "--max-count" => max_count = Some(number(&mut args, &arg)?),
+                "--max-total" => max_total = Some(number(&mut args, &arg)?),
+                "--timeout" => timeout = Some(cancel::parse_duration(&value(&mut args, &arg)?)?),
+                "-j" | "--threads" => threads = number(&mut args, &arg)?.max(1),
+                "--aggregate" => aggregate = aggregate.or(Some(GroupBy::Match)),
+                "--group-by" => aggregate = Some(GroupBy::parse(&value(&mut args, &arg)?)?),
+                "--top" => top = number(&mut args, &arg)?,
+                "--scope" => scope = Some(Scope::parse(&value(&mut args, &arg)?)?),
+                "--no-mmap" => mmap = false,
+                "--html" => html = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--cache" => cache = true,
+                // turns off a `--cache` from the config file
+                "--no-cache" => cache = false,
+                "--cache-dir" => cache_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--cache-size" => cache_size = cache::parse_size(&value(&mut args, &arg)?)?,
+                "--git-history" => git_history = true,
+                "--csv" => delimiter = Some(b','),
+                "--tsv" => delimiter = Some(b'\t'),
+                "--field" => fields.push(value(&mut args, &arg)?),
+                "--json-path" => {
+                    let path = value(&mut args, &arg)?;
+                    json_path = Some(JsonPath::parse(&path).map_err(|e| e.to_string())?);
+                }
+                "--since" => since = Some(value(&mut args, &arg)?),
+                "--until" => until = Some(value(&mut args, &arg)?),
+                "--time-format" => time_format = Some(value(&mut args, &arg)?),
+                "--sorted" => sorted = true,
+                "-f" | "--follow" => follow = true,
+                "--bool" => boolean = true,
+                "--bool-scope" => {
+                    file_scope = match value(&mut args, &arg)?.as_str() {
+                        "line" => Some(false),
+                        "file" => Some(true),
+                        other => {
+                            return Err(format!("unknown scope '{}', use line or file", other))
+                        }
+                    }
+                }
+                "--format" => format = Some(Format::parse(&value(&mut args, &arg)?)?),
+                "--budget-bytes" => {
+                    budget_bytes = Some(cache::parse_size(&value(&mut args, &arg)?)?)
+                }
+                "--budget-matches" => budget_matches = Some(number(&mut args, &arg)?),
+                "--matcher" => matcher = Some(value(&mut args, &arg)?),
+                "--plugin-dir" => plugin_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--sort" | "--sortr" => {
+                    sort = Some(Sort {
+                        by: SortBy::parse(&value(&mut args, &arg)?)?,
+                        reverse: arg == "--sortr",
+                    })
+                }
+                // everything after `--` is positional, even if it starts with '-'
+                "--" => positional.extend(args.by_ref()),
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option '{}'", flag));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let query = positional.next().ok_or("Didn't get a query string")?;
+        let mut paths: Vec<String> = positional.collect();
+        if git_history {
+            // the repository is found from the current directory by default
+            if paths.is_empty() {
+                paths.push(String::from("."));
+            }
+            if paths.len() > 1 {
+                return Err(String::from("--git-history searches one repository"));
+            }
+            if matcher.is_some() || scope.is_some() {
+                return Err(String::from(
+                    "--git-history can't be used with --matcher or --scope",
+                ));
+            }
+            // every match names its commit, path and line
+            line_number = true;
+        } else if paths.is_empty() {
+            return Err(String::from("Didn't get a file name"));
+        }
+
+        if !fields.is_empty() && delimiter.is_none() {
+            return Err(String::from("--field needs --csv or --tsv"));
+        }
+        if delimiter.is_some() && (multiline || matcher.is_some() || git_history) {
+            return Err(String::from(
+                "--csv and --tsv can't be used with --multiline, --matcher or --git-history",
+            ));
+        }
+        if json_path.is_some()
+            && (delimiter.is_some() || multiline || matcher.is_some() || git_history)
+        {
+            return Err(String::from(
+                "--json-path can't be used with --csv, --tsv, --multiline, --matcher or --git-history",
+            ));
+        }
+        // a matcher looks at one line at a time
+        if matcher.is_some() && multiline {
+            return Err(String::from("--matcher can't be used with --multiline"));
+        }
+        // read once every option is in, `--time-format` may come after the bounds
+        let window = if since.is_some() || until.is_some() {
+            Some(Window::new(
+                since.as_deref(),
+                until.as_deref(),
+                time_format,
+                sorted,
+            )?)
+        } else if time_format.is_some() || sorted {
+            return Err(String::from(
+                "--time-format and --sorted need --since or --until",
+            ));
+        } else {
+            None
+        };
+        if window.is_some()
+            && (multiline
+                || delimiter.is_some()
+                || json_path.is_some()
+                || matcher.is_some()
+                || git_history)
+        {
+            return Err(String::from(
+                "--since and --until can't be used with --multiline, --csv, --tsv, --json-path, --matcher or --git-history",
+            ));
+        }
+
+        // a followed file is read a line at a time, as it grows
+        if follow
+            && (multiline
+                || decompress
+                || git_history
+                || html.is_some()
+                || sort.is_some()
+                || scope.is_some()
+                || delimiter.is_some()
+                || json_path.is_some()
+                || window.is_some())
+        {
+            return Err(String::from(
+                "--follow can't be used with --multiline, --decompress, --git-history, --html, --sort, --scope, --csv, --tsv, --json-path, --since or --until",
+            ));
         }
 
-        let query = args[1].clone();
-        let filename = args[2].clone();
+        // every record is one match on one line
+        if format.is_some()
+            && (multiline || aggregate.is_some() || html.is_some() || delimiter.is_some())
+        {
+            return Err(String::from(
+                "--format can't be used with --multiline, --aggregate, --html, --csv or --tsv",
+            ));
+        }
+
+        if file_scope.is_some() && !boolean {
+            return Err(String::from("--bool-scope needs --bool"));
+        }
+        let file_scope = file_scope.unwrap_or(false);
+        // the expression decides for each line or file, its terms are plain text
+        if boolean
+            && (regex
+                || multiline
+                || matcher.is_some()
+                || delimiter.is_some()
+                || json_path.is_some()
+                || git_history
+                || window.is_some())
+        {
+            return Err(String::from(
+                "--bool can't be used with --regex, --multiline, --matcher, --csv, --tsv, --json-path, --git-history, --since or --until",
+            ));
+        }
+        if file_scope && follow {
+            return Err(String::from(
+                "--bool-scope file can't be used with --follow",
+            ));
+        }
 
         // set envoriment viariable
         let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+        let plan = if boolean {
+            Some(Plan::compile(&query, !case_sensitive).map_err(|e| e.to_string())?)
+        } else {
+            None
+        };
 
         Ok(Config {
             query,
-            filename,
+            paths,
             case_sensitive,
+            line_number,
+            decompress,
+            regex,
+            multiline,
+            stats,
+            no_messages,
+            globs,
+            types,
+            types_not,
+            type_add,
+            max_count,
+            max_total,
+            timeout,
+            threads,
+            aggregate,
+            top,
+            scope,
+            mmap,
+            sort,
+            html,
+            cache,
+            cache_dir,
+            cache_size,
+            matcher,
+            plugin_dir,
+            git_history,
+            budget_bytes,
+            budget_matches,
+            delimiter,
+            fields,
+            json_path,
+            window,
+            follow,
+            format,
+            plan,
+            file_scope,
         })
     }
+
+    /// which files inside the searched directories are looked at
+    pub fn filter(&self) -> Result<Filter, Box<dyn Error>> {
+        let mut defs = Types::new();
+        for def in &self.type_add {
+            defs.add(def)?;
+        }
+
+        let mut filter = Filter::new(&self.globs)?;
+        for name in &self.types {
+            for glob in defs.globs(name)? {
+                filter.include(glob)?;
+            }
+        }
+        for name in &self.types_not {
+            for glob in defs.globs(name)? {
+                filter.exclude(glob)?;
+            }
+        }
+        Ok(filter)
+    }
+
+    // everything that changes what a search finds in one file, and where it was
+    // started from since the paths are relative to that
+    fn cache_key(&self) -> String {
+        format!(
+            "{:?} regex={} bool={} file_scope={} case_sensitive={} multiline={} decompress={} max_count={:?} scope={:?} matcher={:?} cwd={:?}",
+            self.query,
+            self.regex,
+            self.plan.is_some(),
+            self.file_scope,
+            self.case_sensitive,
+            self.multiline,
+            self.decompress,
+            self.max_count,
+            self.scope,
+            self.matcher,
+            env::current_dir().unwrap_or_default()
+        )
+    }
+
+    /// compile the query, either as a regular expression or as plain text.
+    /// with `--matcher` the query goes to the plugin, and with a `--json-path`
+    /// comparison it's a number, then every line matches whole. with `--bool`
+    /// it finds the lines that may match, and the plan decides
+    pub fn pattern(&self) -> Result<Regex, RegexError> {
+        if let Some(plan) = &self.plan {
+            return Ok(plan.pattern());
+        }
+        let compares = self.json_path.is_some() && jsonpath::comparison(&self.query).is_some();
+        if self.matcher.is_some() || compares {
+            Regex::new("^.*$", false)
+        } else if self.regex {
+            Regex::new(&self.query, !self.case_sensitive)
+        } else {
+            Ok(Regex::literal(&self.query, !self.case_sensitive))
+        }
+    }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+pub fn run(config: Config) -> Result<Stats, Box<dyn Error>> {
+    run_with(config, &budget::Stderr)
+}
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+/// `run`, with the warnings about `--budget-bytes` and `--budget-matches` sent to `messenger`
+pub fn run_with(config: Config, messenger: &dyn Messenger) -> Result<Stats, Box<dyn Error>> {
+    let started = Instant::now();
+    let pattern = config.pattern()?;
+    if let Some(GroupBy::Capture(n)) = config.aggregate {
+        if n > pattern.groups() {
+            return Err(format!(
+                "can't group by capture group {}, the pattern only has {}",
+                n,
+                pattern.groups()
+            )
+            .into());
+        }
+    }
+    let cancel = match config.timeout {
+        Some(timeout) => Cancel::with_timeout(timeout),
+        None => Cancel::new(),
+    };
+    if config.git_history {
+        return run_history(&config, &pattern, &cancel, messenger, started);
+    }
+    let matcher = match &config.matcher {
+        Some(name) => Some(load_matcher(&config, name)?),
+        None => None,
+    };
+    if config.follow {
+        return run_follow(
+            &config,
+            &pattern,
+            &cancel,
+            matcher.as_ref(),
+            messenger,
+            started,
+        );
+    }
+    let mut files = walk::files(&config.paths, &config.filter()?);
+    if let Some(sort) = config.sort {
+        files = sort::sort_files(files, sort);
+    }
+    // `--html` needs the lines around each match, `--csv` the header and
+    // `--json-path` the count of invalid lines, and those aren't cached.
+    // nor are results for a window, which may be relative to now, or the
+    // match positions `--format` needs. `--scope` counts only some of the
+    // matches on a line, which the cache doesn't keep either
+    let cache = if config.cache
+        && config.html.is_none()
+        && config.delimiter.is_none()
+        && config.json_path.is_none()
+        && config.window.is_none()
+        && config.format.is_none()
+        && config.scope.is_none()
+    {
+        let dir = config.cache_dir.clone().unwrap_or_else(cache::default_dir);
+        Some(Cache::open(&dir, &config.cache_key(), config.cache_size))
     } else {
-        search_case_insensitive(&config.query, &contents)
+        None
     };
 
-    for line in results {
-        println!("{}", line);
+    let stdout = io::stdout();
+    let mut printer = Printer {
+        config: &config,
+        pattern: &pattern,
+        out: BufWriter::new(stdout.lock()),
+        // like grep, name the file in front of each line once there is more than one
+        show_path: files.len() > 1 || config.paths.iter().any(|p| Path::new(p).is_dir()),
+        remaining: config.max_total.unwrap_or(usize::MAX),
+        stats: Stats::default(),
+        counter: Counter::new(),
+        report: Report::new(),
+        budget: Budget::new(messenger, config.budget_bytes, config.budget_matches),
+    };
+    let mut write_error = None;
+
+    // workers take files off a shared queue and send back what they found,
+    // only this thread prints so lines from different files never interleave
+    let queue = Mutex::new(files.into_iter().enumerate());
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads {
+            let tx = tx.clone();
+            let (queue, config, pattern, cancel) = (&queue, &config, &pattern, &cancel);
+            let (cache, matcher) = (cache.as_ref(), matcher.as_ref());
+
+            scope.spawn(move || loop {
+                let (index, entry) = match queue.lock().unwrap().next() {
+                    Some(entry) if !cancel.is_cancelled() => entry,
+                    _ => break,
+                };
+                let found = match entry {
+                    Ok(file) => search_file(config, pattern, cancel, cache, matcher, &file)
+                        .map_err(|e| format!("{}: {}", file.display(), e)),
+                    Err(e) => Err(e.to_string()),
+                };
+                if tx.send((index, found)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // with `--sort`, a file that finishes early waits here until
+        // every file before it has been printed
+        let mut pending = HashMap::new();
+        let mut next = 0;
+        let mut ready = Vec::new();
+
+        'results: for (index, found) in rx {
+            if config.sort.is_some() {
+                pending.insert(index, found);
+                while let Some(found) = pending.remove(&next) {
+                    ready.push(found);
+                    next += 1;
+                }
+            } else {
+                ready.push(found);
+            }
+
+            for found in ready.drain(..) {
+                if let Err(e) = printer.found(found) {
+                    // dropping `rx` on the way out tells the workers to stop
+                    cancel.cancel();
+                    write_error = Some(e);
+                    break 'results;
+                }
+                if printer.remaining == 0 || printer.stats.over_budget {
+                    cancel.cancel();
+                }
+            }
+        }
+
+        // after a timeout some files never arrive, print what came in after them in order
+        let mut rest: Vec<_> = pending.into_iter().collect();
+        rest.sort_by_key(|&(index, _)| index);
+        for (_, found) in rest {
+            if write_error.is_some() {
+                break;
+            }
+            write_error = printer.found(found).err();
+        }
+    });
+
+    if let Some(cache) = &cache {
+        // a cache that can't be written only costs time on the next run
+        if let Err(e) = cache.save() {
+            if !config.no_messages {
+                eprintln!("minigrep: can't save the cache: {}", e);
+            }
+        }
     }
 
-    Ok(())
+    let mut stats = std::mem::take(&mut printer.stats);
+    if cancel.timed_out() {
+        stats.timed_out = true;
+        eprintln!(
+            "minigrep: search cut short after {:?}, results are incomplete",
+            config.timeout.unwrap_or_default()
+        );
+    }
+
+    stats.elapsed = started.elapsed();
+    let written = match write_error {
+        Some(e) => Err(e),
+        None => printer.finish(&stats),
+    };
+    match written.and_then(|_| printer.out.flush()) {
+        // the reader went away, e.g. `minigrep ... | head`, which is not an error
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(stats),
+    }
+}
+
+// `--git-history`: search the files of every commit reachable from HEAD, newest
+// commit first. each result is printed as `commit:path:line:text`. `--glob`
+// and `-t` apply to the paths in the commits
+fn run_history(
+    config: &Config,
+    pattern: &Regex,
+    cancel: &Cancel,
+    messenger: &dyn Messenger,
+    started: Instant,
+) -> Result<Stats, Box<dyn Error>> {
+    let repo = git::Repo::discover(Path::new(&config.paths[0]))?;
+    let commits = repo.history(repo.head()?)?;
+    let filter = config.filter()?;
+
+    let stdout = io::stdout();
+    let mut printer = Printer {
+        config,
+        pattern,
+        out: BufWriter::new(stdout.lock()),
+        show_path: true,
+        remaining: config.max_total.unwrap_or(usize::MAX),
+        stats: Stats::default(),
+        counter: Counter::new(),
+        report: Report::new(),
+        budget: Budget::new(messenger, config.budget_bytes, config.budget_matches),
+    };
+    let max = config.max_count.unwrap_or(usize::MAX);
+    // a blob is searched the first time it turns up, later commits reuse the results
+    let mut searched: HashMap<git::Oid, Vec<(usize, String)>> = HashMap::new();
+    let mut trees = HashMap::new();
+
+    let mut written = Ok(());
+    'commits: for (id, commit) in commits {
+        for (path, blob) in repo.files(commit.tree, &mut trees)?.iter() {
+            if cancel.is_cancelled() || printer.remaining == 0 || printer.stats.over_budget {
+                break 'commits;
+            }
+            if !filter.allows_file(Path::new(path)) {
+                continue;
+            }
+            let mut size = 0;
+            if !searched.contains_key(blob) {
+                let data = match repo.read(*blob) {
+                    Ok((_, data)) => data,
+                    Err(e) => {
+                        let error = format!("{}:{}: {}", id, path, e);
+                        written = written.and_then(|_| printer.found(Err(error)));
+                        searched.insert(*blob, Vec::new());
+                        continue;
+                    }
+                };
+                size = data.len() as u64;
+                // binary files have no lines to show
+                let results = match String::from_utf8(data) {
+                    Ok(text) => search_until(pattern, &text, config.multiline, max, cancel)
+                        .into_iter()
+                        .map(|(number, line)| (number, line.to_string()))
+                        .collect(),
+                    Err(_) => Vec::new(),
+                };
+                searched.insert(*blob, results);
+            }
+            let found = FileMatches {
+                path: PathBuf::from(format!("{}:{}", id, path)),
+                size,
+                results: searched[blob].clone(),
+                context: Vec::new(),
+                header: None,
+                invalid_json: 0,
+                spans: all_spans(config, pattern, &searched[blob]),
+                counts: all_counts(pattern, &searched[blob]),
+            };
+            written = written.and_then(|_| printer.file(found));
+            if written.is_err() {
+                break 'commits;
+            }
+        }
+    }
+
+    let mut stats = std::mem::take(&mut printer.stats);
+    if cancel.timed_out() {
+        stats.timed_out = true;
+        eprintln!(
+            "minigrep: search cut short after {:?}, results are incomplete",
+            config.timeout.unwrap_or_default()
+        );
+    }
+    stats.elapsed = started.elapsed();
+    match written
+        .and_then(|_| printer.finish(&stats))
+        .and_then(|_| printer.out.flush())
+    {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(stats),
+    }
+}
+
+// search lines as they are appended to the files, until the search is cancelled
+// or a limit is reached. each line is written out as soon as it's found
+fn run_follow(
+    config: &Config,
+    pattern: &Regex,
+    cancel: &Cancel,
+    matcher: Option<&Matcher>,
+    messenger: &dyn Messenger,
+    started: Instant,
+) -> Result<Stats, Box<dyn Error>> {
+    let mut followers = Vec::new();
+    for path in &config.paths {
+        let follower = follow::Follower::open(Path::new(path), config.line_number)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        followers.push((follower, 0));
+    }
+
+    let stdout = io::stdout();
+    let mut printer = Printer {
+        config,
+        pattern,
+        out: LineWriter::new(stdout.lock()),
+        show_path: followers.len() > 1,
+        remaining: config.max_total.unwrap_or(usize::MAX),
+        stats: Stats::default(),
+        counter: Counter::new(),
+        report: Report::new(),
+        budget: Budget::new(messenger, config.budget_bytes, config.budget_matches),
+    };
+    let max = config.max_count.unwrap_or(usize::MAX);
+
+    let mut written = Ok(());
+    // like `tail -f | grep -m`, stop once every file has had its matches
+    while !cancel.is_cancelled()
+        && printer.remaining > 0
+        && !printer.stats.over_budget
+        && followers.iter().any(|(_, matched)| *matched < max)
+    {
+        let mut idle = true;
+        for (follower, matched) in followers.iter_mut().filter(|(_, matched)| *matched < max) {
+            let found = follower.poll().map(|(size, lines)| {
+                idle &= size == 0;
+                let results: Vec<(usize, String)> = lines
+                    .into_iter()
+                    .filter(|(_, line)| {
+                        pattern.is_match(line)
+                            && matcher.is_none_or(|m| m.is_match(&config.query, line))
+                            && config.plan.as_ref().is_none_or(|plan| plan.matches(line))
+                    })
+                    .take(max - *matched)
+                    .collect();
+                *matched += results.len();
+                // a matcher and `--bool` decide on whole lines, so every match on a kept one counts
+                FileMatches {
+                    path: follower.path.clone(),
+                    size,
+                    spans: all_spans(config, pattern, &results),
+                    counts: all_counts(pattern, &results),
+                    results,
+                    context: Vec::new(),
+                    header: None,
+                    invalid_json: 0,
+                }
+            });
+            // a file that can't be read any more is reported and left alone
+            let found = found.map_err(|e| {
+                *matched = max;
+                format!("{}: {}", follower.path.display(), e)
+            });
+            written = written.and_then(|_| printer.found(found));
+        }
+        if written.is_err() {
+            break;
+        }
+        if idle {
+            thread::sleep(follow::POLL);
+        }
+    }
+
+    // every poll went through the printer as a file of its own
+    let mut stats = std::mem::take(&mut printer.stats);
+    stats.files_scanned = followers.len();
+    stats.files_matched = followers.iter().filter(|(_, m)| *m > 0).count();
+    stats.elapsed = started.elapsed();
+    match written
+        .and_then(|_| printer.finish(&stats))
+        .and_then(|_| printer.out.flush())
+    {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(stats),
+    }
+}
+
+// the plugin matcher called `name`. plugins that fail to load are only
+// reported, unless one of them may have been the one asked for
+fn load_matcher(config: &Config, name: &str) -> Result<Matcher, Box<dyn Error>> {
+    let dir = config
+        .plugin_dir
+        .clone()
+        .unwrap_or_else(plugin::default_dir);
+    // SAFETY: the plugin directory is the user's own choice of code to run,
+    // like a shell's PATH; `load_dir` checks the ABI version before using a table
+    let (matchers, errors) = unsafe { plugin::load_dir(&dir) };
+    let found = matchers.into_iter().find(|matcher| matcher.name == name);
+    if !config.no_messages || found.is_none() {
+        for e in &errors {
+            eprintln!("minigrep: {}", e);
+        }
+    }
+    found.ok_or_else(|| {
+        format!(
+            "no plugin in {} has a matcher called '{}'",
+            dir.display(),
+            name
+        )
+        .into()
+    })
+}
+
+// the value that follows an option such as `--glob`
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option '{}' needs a value", flag))
+}
+
+fn number(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<usize, String> {
+    let value = value(args, flag)?;
+    value
+        .parse()
+        .map_err(|_| format!("option '{}' needs a number, got '{}'", flag, value))
+}
+
+// `MINIGREP_CONFIG` names a file of default arguments, one per line.
+// blank lines and lines starting with '#' are skipped
+fn config_file_args() -> Result<Vec<String>, String> {
+    let path = match env::var("MINIGREP_CONFIG") {
+        Ok(path) => path,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let contents =
+        fs::read_to_string(&path).map_err(|e| format!("can't read config file {}: {}", path, e))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+// what a worker found in one file
+struct FileMatches {
+    path: PathBuf,
+    size: u64,
+    results: Vec<(usize, String)>,
+    // lines around the results, only collected for `--html`
+    context: Vec<(usize, String)>,
+    // with `--csv` or `--tsv`, printed before the first result
+    header: Option<(usize, String)>,
+    // with `--json-path`, lines that couldn't be parsed
+    invalid_json: usize,
+    // with `--format`, where the matches of each result are within it
+    spans: Vec<Vec<(usize, usize)>>,
+    // how many matches the search accepted in each result
+    counts: Vec<usize>,
+}
+
+// with `--format`, every match of the pattern in each result, for the searches
+// that don't turn any of them down
+fn all_spans(
+    config: &Config,
+    pattern: &Regex,
+    results: &[(usize, String)],
+) -> Vec<Vec<(usize, usize)>> {
+    match config.format {
+        Some(_) => results
+            .iter()
+            .map(|(_, line)| pattern.find_iter(line).collect())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// every match of the pattern in each result, for the same searches
+fn all_counts(pattern: &Regex, results: &[(usize, String)]) -> Vec<usize> {
+    results
+        .iter()
+        .map(|(_, line)| pattern.find_iter(line).count())
+        .collect()
+}
+
+fn search_file(
+    config: &Config,
+    pattern: &Regex,
+    cancel: &Cancel,
+    cache: Option<&Cache>,
+    matcher: Option<&Matcher>,
+    file: &Path,
+) -> Result<FileMatches, Box<dyn Error>> {
+    let max = config.max_count.unwrap_or(usize::MAX);
+    let rust = file.extension().is_some_and(|ext| ext == "rs");
+    let matches = |size, results: Vec<(usize, String)>| FileMatches {
+        path: file.to_path_buf(),
+        size,
+        spans: all_spans(config, pattern, &results),
+        counts: all_counts(pattern, &results),
+        results,
+        context: Vec::new(),
+        header: None,
+        invalid_json: 0,
+    };
+
+    // a file that hasn't changed since its results were cached isn't even opened
+    let stamp = cache.and_then(|_| Stamp::of(file));
+    if let (Some(cache), Some(stamp)) = (cache, stamp) {
+        if let Some(results) = cache.unchanged(file, stamp) {
+            return Ok(matches(0, results));
+        }
+    }
+    // only a search that got to the end of the file is worth keeping
+    let remember = |hash: Option<u64>, results: &[(usize, String)]| {
+        if let (Some(cache), Some(stamp), Some(hash)) = (cache, stamp, hash) {
+            if !cancel.is_cancelled() {
+                cache.store(file, stamp, hash, results);
+            }
+        }
+    };
+
+    // a big file is searched a block at a time straight from a memory map,
+    // unless something needs all of its text at once
+    let whole_text = config.decompress
+        || config.multiline
+        || config.html.is_some()
+        || matcher.is_some()
+        || config.delimiter.is_some()
+        || config.json_path.is_some()
+        || config.window.is_some()
+        || config.plan.is_some()
+        || (config.scope.is_some() && rust);
+    if config.mmap && !whole_text {
+        if let Some((size, results, hash)) =
+            search_mapped(pattern, file, max, cancel, cache.is_some())
+        {
+            remember(hash, &results);
+            return Ok(matches(size, results));
+        }
+    }
+
+    let (size, contents) = read_contents(file, config.decompress)?;
+    let hash = cache.map(|_| cache::fingerprint(contents.as_bytes()));
+    if let (Some(cache), Some(stamp), Some(hash)) = (cache, stamp, hash) {
+        if let Some(results) = cache.same_content(file, stamp, hash) {
+            return Ok(matches(size, results));
+        }
+    }
+
+    if let Some(delimiter) = config.delimiter {
+        let (header, results, counts) =
+            csv::search(pattern, &contents, delimiter, &config.fields, max, cancel)?;
+        return Ok(FileMatches {
+            header,
+            counts,
+            ..matches(size, results)
+        });
+    }
+
+    if let Some(path) = &config.json_path {
+        let (results, counts, invalid_json) =
+            jsonpath::search(path, &config.query, pattern, &contents, max, cancel);
+        return Ok(FileMatches {
+            invalid_json,
+            counts,
+            ..matches(size, results)
+        });
+    }
+
+    if let Some(window) = &config.window {
+        let results = timestamp::search(window, pattern, &contents, max, cancel);
+        return Ok(matches(size, results));
+    }
+
+    // a file the expression doesn't hold for has no lines to show
+    let plan = config.plan.as_ref();
+    if config.file_scope && plan.is_some_and(|plan| !plan.matches(&contents)) {
+        remember(hash, &[]);
+        return Ok(matches(size, Vec::new()));
+    }
+
+    // `--scope` only means something for Rust, other files are searched as a whole
+    let regions = match config.scope {
+        Some(scope) if rust => Some((scope, scope::lex(&contents))),
+        _ => None,
+    };
+    let in_scope = |start, end| match &regions {
+        Some((scope, regions)) => regions.contains(start, end, *scope),
+        None => true,
+    };
+    // with a matcher the pattern matches every line, and the plugin picks
+    let accept = |start, end| {
+        in_scope(start, end)
+            && matcher.is_none_or(|matcher| matcher.is_match(&config.query, &contents[start..end]))
+            && (config.file_scope
+                || plan.is_none_or(|plan| plan.matches(line_around(&contents, start, end))))
+    };
+    let keep: Option<&dyn Fn(usize, usize) -> bool> = match (&regions, matcher, plan) {
+        (None, None, None) => None,
+        _ => Some(&accept),
+    };
+
+    let found = search_in(pattern, &contents, config.multiline, max, cancel, keep);
+    let context = match config.html {
+        Some(_) => html::context(&contents, &found),
+        None => Vec::new(),
+    };
+    // only the matches `keep` accepted are counted, and get a record of their own
+    let spans: Vec<Vec<(usize, usize)>> = found
+        .iter()
+        .map(|(_, line)| {
+            let offset = line.as_ptr() as usize - contents.as_ptr() as usize;
+            pattern
+                .find_iter(line)
+                .filter(|&(start, end)| keep.is_none_or(|keep| keep(offset + start, offset + end)))
+                .collect()
+        })
+        .collect();
+    let counts = spans.iter().map(Vec::len).collect();
+    let spans = match config.format {
+        Some(_) => spans,
+        None => Vec::new(),
+    };
+    let results: Vec<(usize, String)> = found
+        .into_iter()
+        .map(|(number, text)| (number, text.to_string()))
+        .collect();
+    remember(hash, &results);
+
+    Ok(FileMatches {
+        path: file.to_path_buf(),
+        size,
+        results,
+        context,
+        header: None,
+        invalid_json: 0,
+        spans,
+        counts,
+    })
+}
+
+// size on disk, results and the cache fingerprint of a mapped file
+type Mapped = (u64, Vec<(usize, String)>, Option<u64>);
+
+// `None` when the file isn't worth mapping, can't be mapped, is compressed or shrank
+// while it was searched; the ordinary read then takes over.
+// with `hash` set the mapped text is fingerprinted for the cache as well
+fn search_mapped(
+    pattern: &Regex,
+    file: &Path,
+    max: usize,
+    cancel: &Cancel,
+    hash: bool,
+) -> Option<Mapped> {
+    let file = File::open(file).ok()?;
+    // SAFETY: the map is only read through `Mmap::block`, which checks the size
+    // first; like any tool that maps files, this can't stop another process
+    // truncating the file while a block of it is searched
+    let map = unsafe { mmap::Mmap::open(&file) }?;
+    if inflate::detect(map.block(&file, 0, 2).ok()?).is_some() {
+        return None;
+    }
+    let results = mmap::search(pattern, &file, &map, max, cancel).ok()?;
+    // a file that grew has more than the map to fingerprint
+    let unchanged = file
+        .metadata()
+        .is_ok_and(|meta| meta.len() == map.len() as u64);
+    let hash = if hash && unchanged {
+        map.fingerprint(&file).ok()
+    } else {
+        None
+    };
+    Some((map.len() as u64, results, hash))
+}
+
+// writes what the workers found, in the order it arrives
+struct Printer<'a, W: Write> {
+    config: &'a Config,
+    pattern: &'a Regex,
+    out: W,
+    show_path: bool,
+    // results still allowed by `--max-total`
+    remaining: usize,
+    stats: Stats,
+    // filled instead of printing lines when aggregating
+    counter: Counter,
+    // filled instead of printing lines for `--html`
+    report: Report,
+    budget: Budget<'a>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    // a file that can't be read is reported and skipped, the rest are still searched
+    fn found(&mut self, found: Result<FileMatches, String>) -> io::Result<()> {
+        match found {
+            Ok(found) => self.file(found),
+            Err(e) => {
+                self.stats.errors += 1;
+                if !self.config.no_messages {
+                    eprintln!("minigrep: {}", e);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // print at most `remaining` results of one file and count them
+    fn file(&mut self, found: FileMatches) -> io::Result<()> {
+        // files still on their way once the budget ran out are dropped
+        if self.stats.over_budget {
+            return Ok(());
+        }
+        self.stats.files_scanned += 1;
+        self.stats.bytes_read += found.size;
+        if found.invalid_json > 0 {
+            self.stats.invalid_json += found.invalid_json;
+            if !self.config.no_messages {
+                eprintln!(
+                    "minigrep: {}: skipped {} line{} that {} valid JSON",
+                    found.path.display(),
+                    found.invalid_json,
+                    if found.invalid_json == 1 { "" } else { "s" },
+                    if found.invalid_json == 1 {
+                        "isn't"
+                    } else {
+                        "aren't"
+                    }
+                );
+            }
+        }
+        if self
+            .budget
+            .update(self.stats.bytes_read, self.stats.matches)
+        {
+            self.stats.over_budget = true;
+            return Ok(());
+        }
+
+        let take = found.results.len().min(self.remaining);
+        self.remaining -= take;
+        if take > 0 {
+            self.stats.files_matched += 1;
+        }
+
+        if self.config.html.is_some() {
+            self.report
+                .add(&found.path, &found.results[..take], &found.context);
+        }
+        let printed = self.config.aggregate.is_none() && self.config.html.is_none();
+        if let Some((number, header)) = &found.header {
+            if take > 0 && printed {
+                for (offset, line) in header.lines().enumerate() {
+                    self.write_line(&found.path, number + offset, line)?;
+                }
+            }
+        }
+
+        let mut spans = found.spans.into_iter();
+        let mut counts = found.counts.into_iter();
+        for (number, text) in found.results.into_iter().take(take) {
+            let spans = spans.next().unwrap_or_default();
+            if self.stats.over_budget {
+                break;
+            }
+            let matches = self.stats.matches + counts.next().unwrap_or(0);
+            // the result that reaches the limit is shown, one that goes past it isn't
+            if self.budget.past_matches(matches) {
+                self.budget.stop_matches(self.stats.matches);
+                self.stats.over_budget = true;
+                break;
+            }
+            self.stats.over_budget = self.budget.update(self.stats.bytes_read, matches);
+            self.stats.matches = matches;
+            if let Some(group_by) = self.config.aggregate {
+                let file = found.path.display().to_string();
+                self.counter
+                    .add_matches(self.pattern, &text, group_by, &file);
+            }
+            if self.config.aggregate.is_some() || self.config.html.is_some() {
+                self.stats.matched_lines += text.lines().count();
+                continue;
+            }
+            // a multiline result is printed line by line, each with its own number
+            for (offset, line) in text.lines().enumerate() {
+                self.stats.matched_lines += 1;
+                match &self.config.format {
+                    Some(format) => {
+                        let path = found.path.display().to_string();
+                        // a line always has a record, even when the pattern
+                        // itself doesn't show where
+                        let at: &[(usize, usize)] =
+                            if spans.is_empty() { &[(0, 0)] } else { &spans };
+                        for &at in at {
+                            format.write(&mut self.out, &path, number + offset, line, at)?;
+                        }
+                    }
+                    None => self.write_line(&found.path, number + offset, line)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, path: &Path, number: usize, line: &str) -> io::Result<()> {
+        if self.show_path {
+            write!(self.out, "{}:", path.display())?;
+        }
+        if self.config.line_number {
+            write!(self.out, "{}:", number)?;
+        }
+        writeln!(self.out, "{}", line)
+    }
+
+    // everything printed once the search is over
+    fn finish(&mut self, stats: &Stats) -> io::Result<()> {
+        if let Some(group_by) = self.config.aggregate {
+            let table = Table {
+                counter: &self.counter,
+                group_by,
+                top: self.config.top,
+            };
+            write!(self.out, "{}", table)?;
+        }
+        if let Some(path) = &self.config.html {
+            let page = File::create(path).and_then(|mut page| {
+                self.report
+                    .write(&mut page, &self.config.query, self.pattern, stats)
+            });
+            page.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        }
+        if self.config.stats {
+            writeln!(self.out, "\n{}", stats)?;
+        }
+        Ok(())
+    }
+}
+
+// gzip and zlib files are inflated first, so line numbers refer to the decompressed text.
+// also returns the size of the file on disk
+pub(crate) fn read_contents(
+    filename: &Path,
+    decompress: bool,
+) -> Result<(u64, String), Box<dyn Error>> {
+    let bytes = fs::read(filename)?;
+    let size = bytes.len() as u64;
+    let bytes = inflate::decompress(bytes, decompress)?;
+    Ok((size, String::from_utf8(bytes)?))
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
@@ -75,6 +1336,132 @@ pub fn search_case_insensitive<'a>(
     results
 }
 
+/// like `search`, but keeps the 1-based line number of every match
+pub fn search_lines<'a>(
+    query: &str,
+    contents: &'a str,
+    case_sensitive: bool,
+) -> Vec<(usize, &'a str)> {
+    search_pattern(&Regex::literal(query, !case_sensitive), contents)
+}
+
+/// every line that `pattern` matches, with its 1-based line number
+pub fn search_pattern<'a>(pattern: &Regex, contents: &'a str) -> Vec<(usize, &'a str)> {
+    search_until(pattern, contents, false, usize::MAX, &Cancel::new())
+}
+
+/// `search_pattern`, or `search_multiline` when `multiline` is set, that stops after
+/// `max` results or as soon as `cancel` fires, keeping whatever it found until then
+pub fn search_until<'a>(
+    pattern: &Regex,
+    contents: &'a str,
+    multiline: bool,
+    max: usize,
+    cancel: &Cancel,
+) -> Vec<(usize, &'a str)> {
+    search_in(pattern, contents, multiline, max, cancel, None)
+}
+
+// the line a match at `start..end` is on
+fn line_around(contents: &str, start: usize, end: usize) -> &str {
+    let start = contents[..start].rfind('\n').map_or(0, |i| i + 1);
+    let end = contents[end..]
+        .find('\n')
+        .map_or(contents.len(), |i| end + i);
+    &contents[start..end]
+}
+
+// `keep` gets the byte range of each match within `contents`,
+// matches it turns down don't count
+fn search_in<'a>(
+    pattern: &Regex,
+    contents: &'a str,
+    multiline: bool,
+    max: usize,
+    cancel: &Cancel,
+    keep: Option<&dyn Fn(usize, usize) -> bool>,
+) -> Vec<(usize, &'a str)> {
+    if multiline {
+        return multiline_spans(pattern, contents, max, cancel, keep);
+    }
+
+    contents
+        .lines()
+        .enumerate()
+        .take_while(|_| !cancel.is_cancelled())
+        .filter(|(_, line)| match keep {
+            None => pattern.is_match(line),
+            Some(keep) => {
+                // where the line starts inside `contents`
+                let offset = line.as_ptr() as usize - contents.as_ptr() as usize;
+                pattern
+                    .find_iter(line)
+                    .any(|(start, end)| keep(offset + start, offset + end))
+            }
+        })
+        .take(max)
+        .map(|(index, line)| (index + 1, line))
+        .collect()
+}
+
+/// match `pattern` against the whole of `contents`, so a match may cross line breaks.
+///
+/// each result is the full span of lines a match touches together with the number
+/// of its first line; matches that share a line are merged into one span
+pub fn search_multiline<'a>(pattern: &Regex, contents: &'a str) -> Vec<(usize, &'a str)> {
+    multiline_spans(pattern, contents, usize::MAX, &Cancel::new(), None)
+}
+
+fn multiline_spans<'a>(
+    pattern: &Regex,
+    contents: &'a str,
+    max: usize,
+    cancel: &Cancel,
+    keep: Option<&dyn Fn(usize, usize) -> bool>,
+) -> Vec<(usize, &'a str)> {
+    // (line number, start, end) byte ranges of whole lines
+    let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+    let mut line = 1;
+    let mut counted = 0;
+
+    for (start, end) in pattern.find_iter(contents) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if keep.is_some_and(|keep| !keep(start, end)) {
+            continue;
+        }
+        let span_start = contents[..start].rfind('\n').map_or(0, |i| i + 1);
+        // a match that ends with its newline stops on that line
+        let span_end = if end > start && contents[..end].ends_with('\n') {
+            end - 1
+        } else {
+            contents[end..]
+                .find('\n')
+                .map_or(contents.len(), |i| end + i)
+        };
+
+        if let Some(last) = spans.last_mut() {
+            if span_start <= last.2 {
+                last.2 = last.2.max(span_end);
+                continue;
+            }
+        }
+
+        if spans.len() == max {
+            break;
+        }
+        line += contents[counted..span_start].matches('\n').count();
+        counted = span_start;
+        spans.push((line, span_start, span_end));
+    }
+
+    spans
+        .into_iter()
+        .map(|(line, start, end)| (line, contents[start..end].trim_end_matches('\r')))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +1492,270 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn line_numbers() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (4, "Trust me.")],
+            search_lines("rUsT", contents, false)
+        );
+    }
+
+    #[test]
+    fn config_flags() {
+        let args = ["minigrep", "-n", "duct", "-z", "poem.txt.gz"];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+
+        assert_eq!("duct", config.query);
+        assert_eq!(vec!["poem.txt.gz"], config.paths);
+        assert!(config.line_number);
+        assert!(config.decompress);
+    }
+
+    #[test]
+    fn multiline_spans() {
+        let contents = "\
+mod tests {
+    #[test]
+    fn works() {}
+
+    #[test]
+
+    fn spaced() {}
+}";
+        let pattern = Regex::new(r"#\[test\]\s*fn \w+", false).unwrap();
+
+        assert_eq!(
+            vec![
+                (2, "    #[test]\n    fn works() {}"),
+                (5, "    #[test]\n\n    fn spaced() {}"),
+            ],
+            search_multiline(&pattern, contents)
+        );
+        assert!(search_pattern(&pattern, contents).is_empty());
+    }
+
+    #[test]
+    fn multiline_merges_shared_lines() {
+        let contents = "a b\nc\nd a\nb";
+        let pattern = Regex::new(r"a\s+b", false).unwrap();
+
+        assert_eq!(
+            vec![(1, "a b"), (3, "d a\nb")],
+            search_multiline(&pattern, contents)
+        );
+        let pattern = Regex::new(r"b\nc|c$", false).unwrap();
+        assert_eq!(
+            vec![(1, "a b\nc c")],
+            search_multiline(&pattern, "a b\nc c\nd")
+        );
+    }
+
+    #[test]
+    fn run_reports_stats() {
+        let args = [
+            "minigrep",
+            "-E",
+            "value 1[0-9]?$",
+            "tests/data/values.txt.gz",
+        ];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        let stats = run(config).unwrap();
+
+        assert_eq!(1, stats.files_scanned);
+        assert_eq!(774, stats.bytes_read);
+        assert_eq!(1, stats.files_matched);
+        assert_eq!(22, stats.matched_lines);
+        assert_eq!(22, stats.matches);
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let args = [
+            "minigrep",
+            "-s",
+            "value 1",
+            "tests/data/missing.txt",
+            "tests/data/values.txt.gz",
+        ];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        let stats = run(config).unwrap();
+
+        assert_eq!(1, stats.errors);
+        assert_eq!(1, stats.files_scanned);
+        assert_eq!(1, stats.files_matched);
+        assert_eq!(2, stats.exit_code());
+    }
+
+    #[test]
+    fn filter_from_types() {
+        let args = [
+            "minigrep",
+            "-t",
+            "proto",
+            "-T",
+            "markdown",
+            "--type-add",
+            "proto:*.proto",
+            "x",
+            ".",
+        ];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        let filter = config.filter().unwrap();
+
+        assert!(filter.allows_file(Path::new("api/v1.proto")));
+        assert!(!filter.allows_file(Path::new("README.md")));
+        assert!(!filter.allows_file(Path::new("src/lib.rs")));
+
+        let args = ["minigrep", "-t", "cobol", "x", "."];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        assert!(config.filter().is_err());
+    }
+
+    #[test]
+    fn match_budget() {
+        let matched = |limit: &str| {
+            let args = [
+                "minigrep",
+                "--budget-matches",
+                limit,
+                "value",
+                "tests/data/values.txt.gz",
+            ];
+            let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+            let stats = run(config).unwrap();
+            assert!(stats.over_budget);
+            stats.matched_lines
+        };
+
+        assert_eq!(0, matched("0"));
+        assert_eq!(5, matched("5"));
+    }
+
+    #[test]
+    fn format_only_scoped_matches() {
+        let dir = env::temp_dir().join(format!("minigrep-format-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.rs");
+        fs::write(&file, "fn main() { foo(); } // foo here\n").unwrap();
+        let path = file.display().to_string();
+        let args = [
+            "minigrep", "--scope", "code", "--format", "vimgrep", "foo", &path,
+        ];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        let pattern = config.pattern().unwrap();
+
+        let found = search_file(&config, &pattern, &Cancel::new(), None, None, &file).unwrap();
+        let mut printer = Printer {
+            config: &config,
+            pattern: &pattern,
+            out: Vec::new(),
+            show_path: false,
+            remaining: usize::MAX,
+            stats: Stats::default(),
+            counter: Counter::new(),
+            report: Report::new(),
+            budget: Budget::new(&budget::Stderr, None, None),
+        };
+        printer.file(found).unwrap();
+        // the `foo` in the comment is left out
+        assert_eq!(
+            format!("{}:1:13:fn main() {{ foo(); }} // foo here\n", path),
+            String::from_utf8(printer.out).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_counted_where_the_search_looked() {
+        let dir = env::temp_dir().join(format!("minigrep-counts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("log.csv");
+        fs::write(&csv, "time,status\n1714040400,404\n").unwrap();
+        let json = dir.join("log.json");
+        fs::write(&json, "{\"status\": 404, \"path\": \"/404\"}\n").unwrap();
+        let matches = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args);
+            let config = Config::new(args.map(|s| s.to_string())).unwrap();
+            run(config).unwrap().matches
+        };
+
+        // the timestamp holds a "404" too, but isn't a chosen field
+        let path = csv.display().to_string();
+        assert_eq!(1, matches(&["--csv", "--field", "status", "404", &path]));
+        // a comparison is one match, and the path isn't looked at
+        let path = json.display().to_string();
+        assert_eq!(1, matches(&["--json-path", ".status", ">= 400", &path]));
+        assert_eq!(1, matches(&["--json-path", ".status", "404", &path]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_stops_early() {
+        let contents = "a1\nb\na2\na3\na4";
+        let pattern = Regex::literal("a", false);
+
+        assert_eq!(
+            vec![(1, "a1"), (3, "a2")],
+            search_until(&pattern, contents, false, 2, &Cancel::new())
+        );
+        assert_eq!(
+            vec![(1, "a1")],
+            search_until(&pattern, contents, true, 1, &Cancel::new())
+        );
+
+        let cancel = Cancel::new();
+        cancel.cancel();
+        assert!(search_until(&pattern, contents, false, 10, &cancel).is_empty());
+    }
+
+    #[test]
+    fn max_total_across_files() {
+        let args = [
+            "minigrep",
+            "-j",
+            "3",
+            "-m",
+            "4",
+            "--max-total",
+            "6",
+            "value",
+            "tests/data/values.txt.gz",
+            "tests/data/values.txt.gz",
+            "tests/data/values.txt.gz",
+        ];
+        let config = Config::new(args.iter().map(|s| s.to_string())).unwrap();
+        let stats = run(config).unwrap();
+
+        assert_eq!(6, stats.matched_lines);
+        assert_eq!(2, stats.files_matched);
+        assert!(!stats.timed_out);
+    }
+
+    #[test]
+    fn scoped_search() {
+        let contents = "\
+// TODO: remove unwrap
+let x = y.unwrap(); // fine
+let s = \"unwrap\";";
+        let pattern = Regex::literal("unwrap", false);
+        let regions = scope::lex(contents);
+        let search = |scope| {
+            let keep = |start, end| regions.contains(start, end, scope);
+            search_in(&pattern, contents, false, 10, &Cancel::new(), Some(&keep))
+        };
+
+        assert_eq!(vec![(1, "// TODO: remove unwrap")], search(Scope::Comments));
+        assert_eq!(
+            vec![(2, "let x = y.unwrap(); // fine")],
+            search(Scope::Code)
+        );
+        assert_eq!(vec![(3, "let s = \"unwrap\";")], search(Scope::Strings));
+    }
 }
@@ -1,18 +1,65 @@
 use std::env;
+use std::io;
 use std::process;
 
+use minigrep::diff;
+use minigrep::rpc;
+use minigrep::server::{self, ServeConfig};
 use minigrep::Config;
 
 fn main() {
-    let config = Config::new(&env::args()).unwrap_or_else(|err| {
+    // `minigrep serve ...` runs the HTTP service, to search for the word
+    // "serve" itself use `minigrep -- serve FILE`
+    if env::args().nth(1).as_deref() == Some("serve") {
+        let config = ServeConfig::new(env::args().skip(2)).unwrap_or_else(|err| {
+            eprintln!("Problem parsing arguments: {}", err);
+            process::exit(2);
+        });
+        if let Err(e) = server::serve(config) {
+            eprintln!("Application error: {}", e);
+            process::exit(2);
+        }
+        return;
+    }
+    // `minigrep diff QUERY OLD_DIR NEW_DIR` takes the usual options, and reports
+    // lines that were added, removed or moved instead of every match. to search
+    // for the word "diff" itself use `minigrep -- diff FILE`
+    if env::args().nth(1).as_deref() == Some("diff") {
+        // "diff" stands where the program name usually is, and is skipped with it
+        let config = Config::new(env::args().skip(1)).unwrap_or_else(|err| {
+            eprintln!("Problem parsing arguments: {}", err);
+            process::exit(2);
+        });
+        match diff::run(config) {
+            Ok(stats) => process::exit(stats.exit_code()),
+            Err(e) => {
+                eprintln!("Application error: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+    // editors keep one process around and talk JSON-RPC to it
+    if env::args().nth(1).as_deref() == Some("--stdio-rpc") {
+        if let Err(e) = rpc::serve(io::stdin().lock(), io::stdout()) {
+            eprintln!("Application error: {}", e);
+            process::exit(2);
+        }
+        return;
+    }
+
+    let config = Config::new(env::args()).unwrap_or_else(|err| {
         // print err print to indicated file
         eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        process::exit(2);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {}", e);
+    // 0: something matched, 1: nothing matched, 2: an error happened
+    match minigrep::run(config) {
+        Ok(stats) => process::exit(stats.exit_code()),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
 
-        process::exit(1);
+            process::exit(2);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,275 @@
+//! searching big files through a read-only memory map instead of copying them
+//!
+//! only plain regular files of at least `MIN_SIZE` bytes are mapped. pipes,
+//! devices, compressed files and anything `mmap` refuses go through the
+//! ordinary buffered read instead.
+//!
+//! touching a mapped page past the end of a file that was truncated kills the
+//! process with SIGBUS, so the map has no slice of its own to hand out: every
+//! read, down to the check for compression, goes through `Mmap::block`, which
+//! looks at the file's size first. once it has shrunk, the search gives up and
+//! the caller reads the file the ordinary way. a truncation by another process
+//! while a block is in use can't be caught, which is why `Mmap::open` is unsafe
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+
+use crate::cancel::Cancel;
+use crate::regex::Regex;
+
+/// files smaller than this are cheaper to just read
+pub const MIN_SIZE: u64 = 4 * 1024 * 1024;
+
+// bytes searched between two checks of the file size
+const BLOCK: usize = 1024 * 1024;
+
+/// the file got smaller while it was being searched
+#[derive(Debug)]
+pub struct Shrunk;
+
+impl fmt::Display for Shrunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "file shrank while it was being searched")
+    }
+}
+
+impl Error for Shrunk {}
+
+/// a whole file mapped read-only into memory
+pub(crate) struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// the mapping is read-only and owned by this value alone
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// map `file` when it is a regular file worth mapping, `None` otherwise
+    ///
+    /// # Safety
+    ///
+    /// the file mustn't be truncated by anyone while a slice `block` returned
+    /// is still in use. `block` checks the size before handing bytes out, but
+    /// a page cut off after that kills the process with SIGBUS
+    pub(crate) unsafe fn open(file: &File) -> Option<Mmap> {
+        let meta = file.metadata().ok()?;
+        if !meta.is_file() || meta.len() < MIN_SIZE {
+            return None;
+        }
+        Mmap::map(file, usize::try_from(meta.len()).ok()?).ok()
+    }
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn map(file: &File, len: usize) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        // from the C library std already links against
+        extern "C" {
+            fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, off: i64)
+                -> *mut u8;
+        }
+        const PROT_READ: i32 = 1;
+        const MAP_PRIVATE: i32 = 2;
+        // MAP_FAILED is (void *) -1
+        const MAP_FAILED: *mut u8 = !0 as *mut u8;
+
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn map(_file: &File, _len: usize) -> io::Result<Mmap> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "memory maps aren't supported here",
+        ))
+    }
+}
+
+impl Mmap {
+    /// the size of the file when it was mapped
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// bytes `start..end` of the map, once `file` is found to still hold all of
+    /// them. the only way to read the map
+    pub(crate) fn block(
+        &self,
+        file: &File,
+        start: usize,
+        end: usize,
+    ) -> Result<&[u8], Box<dyn Error>> {
+        assert!(start <= end && end <= self.len);
+        if file.metadata()?.len() < self.len as u64 {
+            return Err(Box::new(Shrunk));
+        }
+        // the pages are all there, and stay mapped as long as `self`
+        Ok(unsafe { std::slice::from_raw_parts(self.ptr.add(start), end - start) })
+    }
+
+    /// `cache::fingerprint` of the mapped bytes, read a block at a time
+    pub(crate) fn fingerprint(&self, file: &File) -> Result<u64, Box<dyn Error>> {
+        let mut hash = crate::cache::fingerprint(&[]);
+        let mut start = 0;
+        while start < self.len {
+            let end = (start + BLOCK).min(self.len);
+            hash = crate::cache::fingerprint_more(hash, self.block(file, start, end)?);
+            start = end;
+        }
+        Ok(hash)
+    }
+}
+
+impl Drop for Mmap {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn drop(&mut self) {
+        extern "C" {
+            fn munmap(addr: *mut u8, len: usize) -> i32;
+        }
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn drop(&mut self) {}
+}
+
+/// `search_until` over the mapped bytes of `file`, one block of whole lines at a time.
+///
+/// fails with `Shrunk` as soon as the file is found smaller than `map`,
+/// and on the first block that isn't valid UTF-8
+pub(crate) fn search(
+    pattern: &Regex,
+    file: &File,
+    map: &Mmap,
+    max: usize,
+    cancel: &Cancel,
+) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
+    let mut results = Vec::new();
+    let mut start = 0;
+    // lines before `start`
+    let mut lines = 0;
+
+    while start < map.len() && results.len() < max && !cancel.is_cancelled() {
+        // blocks end just after a newline, so no line or character is cut in
+        // two. a line longer than a block takes a bigger one
+        let mut size = BLOCK;
+        let bytes = loop {
+            let bytes = map.block(file, start, (start + size).min(map.len()))?;
+            if start + bytes.len() == map.len() {
+                break bytes;
+            }
+            match bytes.iter().rposition(|&b| b == b'\n') {
+                Some(i) => break &bytes[..i + 1],
+                None => size *= 2,
+            }
+        };
+        let end = start + bytes.len();
+        let block = std::str::from_utf8(bytes)?;
+
+        let found = crate::search_until(pattern, block, false, max - results.len(), cancel);
+        results.extend(
+            found
+                .into_iter()
+                .map(|(number, text)| (lines + number, text.to_string())),
+        );
+        lines += block.matches('\n').count();
+        start = end;
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn search_mapped_file() {
+        let path = std::env::temp_dir().join(format!("minigrep-mmap-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        // a few blocks' worth, with the interesting lines far apart
+        let filler = "just some filler text\n".repeat(MIN_SIZE as usize / 22 + 1);
+        write!(
+            file,
+            "needle one\n{}needle two\n{}last needle",
+            filler, filler
+        )
+        .unwrap();
+        drop(file);
+
+        let file = File::open(&path).unwrap();
+        let map = unsafe { Mmap::open(&file) }.expect("file should be big enough to map");
+        let pattern = Regex::literal("needle", false);
+        let filler_lines = filler.matches('\n').count();
+
+        let found = search(&pattern, &file, &map, usize::MAX, &Cancel::new()).unwrap();
+        assert_eq!(
+            vec![
+                (1, String::from("needle one")),
+                (filler_lines + 2, String::from("needle two")),
+                (2 * filler_lines + 3, String::from("last needle")),
+            ],
+            found
+        );
+        assert_eq!(
+            1,
+            search(&pattern, &file, &map, 1, &Cancel::new())
+                .unwrap()
+                .len()
+        );
+
+        let text = fs::read(&path).unwrap();
+        assert_eq!(
+            crate::cache::fingerprint(&text),
+            map.fingerprint(&file).unwrap()
+        );
+
+        // once the file is cut short the map isn't touched again
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(10)
+            .unwrap();
+        let err = search(&pattern, &file, &map, usize::MAX, &Cancel::new()).unwrap_err();
+        assert!(err.is::<Shrunk>());
+        assert!(map.fingerprint(&file).unwrap_err().is::<Shrunk>());
+
+        drop(map);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn small_files_and_pipes_are_not_mapped() {
+        let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        assert!(unsafe { Mmap::open(&file) }.is_none());
+
+        #[cfg(unix)]
+        {
+            let dev = File::open("/dev/null").unwrap();
+            assert!(unsafe { Mmap::open(&dev) }.is_none());
+        }
+    }
+}
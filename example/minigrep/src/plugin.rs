@@ -0,0 +1,288 @@
+//! matchers from shared libraries, picked with `--matcher NAME`
+//!
+//! every library in the plugin directory is loaded and asked for its ABI version
+//! first, through `minigrep_plugin_abi_version`. only when that equals
+//! `ABI_VERSION` is `minigrep_plugin_matchers` called for its table of named
+//! matchers, so a plugin built against another version of
+//! `include/minigrep_plugin.h` is turned away before any of its structs are read.
+//!
+//! a matcher gets the query and one line and says whether the line matches.
+//! lines are searched from several threads at once, so matchers must not keep
+//! state between calls
+
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// the version of `include/minigrep_plugin.h` this build understands
+pub const ABI_VERSION: u32 = 1;
+
+// the structs of `include/minigrep_plugin.h`
+type MatchFn = extern "C" fn(*const c_char, usize, *const c_char, usize) -> i32;
+
+#[repr(C)]
+struct MatcherDef {
+    name: *const c_char,
+    description: *const c_char,
+    matches: Option<MatchFn>,
+}
+
+#[repr(C)]
+struct PluginDef {
+    matcher_count: usize,
+    matchers: *const MatcherDef,
+}
+
+#[derive(Debug)]
+pub struct PluginError {
+    pub path: PathBuf,
+    pub msg: String,
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "plugin {}: {}", self.path.display(), self.msg)
+    }
+}
+
+impl Error for PluginError {}
+
+/// one named matcher out of a loaded plugin
+#[derive(Clone)]
+pub struct Matcher {
+    pub name: String,
+    pub description: String,
+    matches: MatchFn,
+    // keeps the library loaded for as long as the function may be called
+    _library: Arc<Library>,
+}
+
+impl Matcher {
+    pub fn is_match(&self, query: &str, line: &str) -> bool {
+        // the header promises a line without its line break, CRLF ones included
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let query_ptr = query.as_ptr() as *const c_char;
+        let line_ptr = line.as_ptr() as *const c_char;
+        (self.matches)(query_ptr, query.len(), line_ptr, line.len()) != 0
+    }
+}
+
+impl fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Matcher({})", self.name)
+    }
+}
+
+/// where plugins are looked for without `--plugin-dir`
+pub fn default_dir() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir).join("minigrep/plugins"),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config/minigrep/plugins"),
+            None => PathBuf::from("plugins"),
+        },
+    }
+}
+
+/// load every plugin in `dir`. a plugin that can't be loaded or has the wrong
+/// ABI version comes back as an error, the others are still used
+///
+/// # Safety
+///
+/// loading a library runs its initializers, and its functions are called as
+/// the signatures of `include/minigrep_plugin.h`, for the version check and
+/// then by every `Matcher::is_match`. `dir` must only hold libraries that are
+/// trusted to run in this process and were built against that header
+pub unsafe fn load_dir(dir: &Path) -> (Vec<Matcher>, Vec<PluginError>) {
+    let mut matchers = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "so" || ext == "dylib" || ext == "dll")
+            })
+            .collect(),
+        // no plugin directory is the same as an empty one
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+
+    for path in paths {
+        match load(&path) {
+            Ok(found) => matchers.extend(found),
+            Err(msg) => errors.push(PluginError { path, msg }),
+        }
+    }
+    (matchers, errors)
+}
+
+fn load(path: &Path) -> Result<Vec<Matcher>, String> {
+    let library = Arc::new(Library::open(path)?);
+
+    let version = library.symbol("minigrep_plugin_abi_version")?;
+    // every plugin exports these two functions, see include/minigrep_plugin.h
+    let version: extern "C" fn() -> u32 = unsafe { std::mem::transmute(version) };
+    let version = version();
+    if version != ABI_VERSION {
+        return Err(format!(
+            "built for plugin ABI version {}, this minigrep needs version {}",
+            version, ABI_VERSION
+        ));
+    }
+
+    let table = library.symbol("minigrep_plugin_matchers")?;
+    let table: extern "C" fn() -> *const PluginDef = unsafe { std::mem::transmute(table) };
+    let table = table();
+    if table.is_null() {
+        return Err(String::from("no matcher table"));
+    }
+
+    let table = unsafe { &*table };
+    let defs: &[MatcherDef] = if table.matcher_count == 0 {
+        &[]
+    } else if table.matchers.is_null() {
+        return Err(String::from("no matcher table"));
+    } else {
+        unsafe { std::slice::from_raw_parts(table.matchers, table.matcher_count) }
+    };
+
+    let text = |ptr: *const c_char| {
+        if ptr.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+    defs.iter()
+        .map(|def| {
+            let name = text(def.name);
+            let matches = match def.matches {
+                Some(matches) if !name.is_empty() => matches,
+                _ => return Err(String::from("a matcher without a name or function")),
+            };
+            Ok(Matcher {
+                name,
+                description: text(def.description),
+                matches,
+                _library: Arc::clone(&library),
+            })
+        })
+        .collect()
+}
+
+// a shared library opened with dlopen, closed again once nothing uses it
+struct Library {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    handle: *mut u8,
+}
+
+// dlsym and calling into the library may happen from any thread
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+#[cfg(unix)]
+mod dl {
+    use std::os::raw::c_char;
+
+    pub const RTLD_NOW: i32 = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    extern "C" {
+        pub fn dlopen(filename: *const c_char, flag: i32) -> *mut u8;
+        pub fn dlsym(handle: *mut u8, symbol: *const c_char) -> *mut u8;
+        pub fn dlerror() -> *const c_char;
+        pub fn dlclose(handle: *mut u8) -> i32;
+    }
+}
+
+#[cfg(unix)]
+impl Library {
+    fn open(path: &Path) -> Result<Library, String> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        let handle = unsafe { dl::dlopen(name.as_ptr(), dl::RTLD_NOW) };
+        if handle.is_null() {
+            return Err(last_error());
+        }
+        Ok(Library { handle })
+    }
+
+    fn symbol(&self, name: &str) -> Result<*mut u8, String> {
+        let symbol = std::ffi::CString::new(name).unwrap();
+        let found = unsafe { dl::dlsym(self.handle, symbol.as_ptr()) };
+        if found.is_null() {
+            return Err(format!("doesn't export {}", name));
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(unix)]
+fn last_error() -> String {
+    let error = unsafe { dl::dlerror() };
+    if error.is_null() {
+        String::from("can't be loaded")
+    } else {
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            dl::dlclose(self.handle);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Library {
+    fn open(_path: &Path) -> Result<Library, String> {
+        Err(String::from("plugins are only supported on Unix"))
+    }
+
+    fn symbol(&self, _name: &str) -> Result<*mut u8, String> {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_dir_has_no_plugins() {
+        let (matchers, errors) = unsafe { load_dir(Path::new("/no/such/minigrep/plugins")) };
+        assert!(matchers.is_empty());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn not_a_library() {
+        // the .so name alone gets it tried, and dlopen turns it down
+        let dir = std::env::temp_dir().join(format!("minigrep-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("broken.so"), "not a shared library").unwrap();
+
+        // SAFETY: dlopen turns the file down before anything in it runs
+        let (matchers, errors) = unsafe { load_dir(&dir) };
+        assert!(matchers.is_empty());
+        assert_eq!(1, errors.len());
+        assert_eq!(dir.join("broken.so"), errors[0].path);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+//! `--bool`: the query is a boolean expression of words and phrases
+//!
+//! ```text
+//! error AND timeout AND NOT retry
+//! (panic OR "fatal error") NOT test
+//! ```
+//!
+//! `AND`, `OR` and `NOT` are only operators in capitals, words next to each
+//! other are joined by `AND`, which binds tighter than `OR`. a phrase in double
+//! quotes is matched as it is, with `\"` for a quote. terms are plain text, and
+//! follow `CASE_INSENSITIVE` like any query.
+//!
+//! by default a line matches when the expression holds for that line. with
+//! `--bool-scope file` it's the file that has to hold it, with terms anywhere
+//! in it, and in those files the lines with a term are printed.
+//!
+//! the expression is compiled into a `Plan`: repeated terms are looked for once,
+//! nested `AND`s and `OR`s are flattened with the cheapest parts first, and a
+//! pattern of the terms a match needs lets the search skip lines without any
+
+use std::fmt;
+
+use crate::regex::Regex;
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid query at position {}: {}", self.pos, self.msg)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+// the tokens of `query`, each with the position it starts at
+fn tokens(query: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let start = pos;
+        let token = match chars[pos] {
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '(' => {
+                pos += 1;
+                Token::Open
+            }
+            ')' => {
+                pos += 1;
+                Token::Close
+            }
+            '"' => {
+                let mut phrase = String::new();
+                pos += 1;
+                loop {
+                    match chars.get(pos) {
+                        None => {
+                            return Err(QueryError {
+                                pos: start,
+                                msg: "unclosed '\"'",
+                            })
+                        }
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.get(pos + 1), Some('"') | Some('\\')) => {
+                            phrase.push(chars[pos + 1]);
+                            pos += 2;
+                        }
+                        Some(&c) => {
+                            phrase.push(c);
+                            pos += 1;
+                        }
+                    }
+                }
+                pos += 1;
+                if phrase.is_empty() {
+                    return Err(QueryError {
+                        pos: start,
+                        msg: "empty phrase",
+                    });
+                }
+                Token::Term(phrase)
+            }
+            _ => {
+                while pos < chars.len()
+                    && !chars[pos].is_whitespace()
+                    && !matches!(chars[pos], '(' | ')' | '"')
+                {
+                    pos += 1;
+                }
+                let word: String = chars[start..pos].iter().collect();
+                match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                }
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    // an index into the plan's terms
+    Term(usize),
+    Not(Box<Node>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    // where the query ends, for errors at the end
+    end: usize,
+    terms: Vec<String>,
+    ignore_case: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn error(&self, msg: &'static str) -> QueryError {
+        let pos = self.tokens.get(self.next).map_or(self.end, |(pos, _)| *pos);
+        QueryError { pos, msg }
+    }
+
+    // or := and (OR and)*
+    fn or(&mut self) -> Result<Node, QueryError> {
+        let mut items = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            items.push(self.and()?);
+        }
+        Ok(join(items, false))
+    }
+
+    // and := not (AND? not)*
+    fn and(&mut self) -> Result<Node, QueryError> {
+        let mut items = vec![self.not()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.next += 1,
+                Some(Token::Term(_)) | Some(Token::Not) | Some(Token::Open) => {}
+                _ => break,
+            }
+            items.push(self.not()?);
+        }
+        Ok(join(items, true))
+    }
+
+    // not := NOT not | ( or ) | term
+    fn not(&mut self) -> Result<Node, QueryError> {
+        match self.peek().cloned() {
+            Some(Token::Not) => {
+                self.next += 1;
+                Ok(match self.not()? {
+                    Node::Not(inner) => *inner,
+                    node => Node::Not(Box::new(node)),
+                })
+            }
+            Some(Token::Open) => {
+                let open = self.next;
+                self.next += 1;
+                let node = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    self.next = open;
+                    return Err(self.error("unclosed '('"));
+                }
+                self.next += 1;
+                Ok(node)
+            }
+            Some(Token::Term(text)) => {
+                self.next += 1;
+                Ok(Node::Term(self.term(text)))
+            }
+            Some(Token::Close) => Err(self.error("expected a term before ')'")),
+            Some(_) => Err(self.error("expected a term before the operator")),
+            None => Err(self.error("expected a term")),
+        }
+    }
+
+    // the index of a term, the same one for every time it's used
+    fn term(&mut self, text: String) -> usize {
+        let same = |known: &String| match self.ignore_case {
+            true => known.to_lowercase() == text.to_lowercase(),
+            false => *known == text,
+        };
+        match self.terms.iter().position(same) {
+            Some(i) => i,
+            None => {
+                self.terms.push(text);
+                self.terms.len() - 1
+            }
+        }
+    }
+}
+
+// one node for several, with the same operator flattened into it
+fn join(mut items: Vec<Node>, and: bool) -> Node {
+    if items.len() == 1 {
+        return items.pop().unwrap();
+    }
+    let mut flat = Vec::new();
+    for item in items {
+        match item {
+            Node::And(inner) if and => flat.extend(inner),
+            Node::Or(inner) if !and => flat.extend(inner),
+            item => flat.push(item),
+        }
+    }
+    if and {
+        Node::And(flat)
+    } else {
+        Node::Or(flat)
+    }
+}
+
+// how many terms evaluating `node` may look for
+fn cost(node: &Node) -> usize {
+    match node {
+        Node::Term(_) => 1,
+        Node::Not(inner) => cost(inner),
+        Node::And(items) | Node::Or(items) => items.iter().map(cost).sum(),
+    }
+}
+
+// cheap parts first, so `AND` and `OR` can often stop before the costly ones
+fn order(node: &mut Node) {
+    match node {
+        Node::Term(_) => {}
+        Node::Not(inner) => order(inner),
+        Node::And(items) | Node::Or(items) => {
+            items.iter_mut().for_each(order);
+            items.sort_by_key(cost);
+        }
+    }
+}
+
+/// a compiled query
+pub struct Plan {
+    terms: Vec<String>,
+    matchers: Vec<Regex>,
+    root: Node,
+    ignore_case: bool,
+}
+
+impl Plan {
+    pub fn compile(query: &str, ignore_case: bool) -> Result<Plan, QueryError> {
+        let mut parser = Parser {
+            tokens: tokens(query)?,
+            next: 0,
+            end: query.chars().count(),
+            terms: Vec::new(),
+            ignore_case,
+        };
+        let mut root = parser.or()?;
+        // terms next to each other are joined, so only a ')' can be left over
+        if parser.peek().is_some() {
+            return Err(parser.error("unmatched ')'"));
+        }
+        order(&mut root);
+
+        let matchers = parser
+            .terms
+            .iter()
+            .map(|term| Regex::literal(term, ignore_case))
+            .collect();
+        Ok(Plan {
+            terms: parser.terms,
+            matchers,
+            root,
+            ignore_case,
+        })
+    }
+
+    /// whether the expression holds for `text`, a line or a whole file
+    pub fn matches(&self, text: &str) -> bool {
+        let mut found = vec![None; self.terms.len()];
+        self.eval(&self.root, &mut |i| {
+            *found[i].get_or_insert_with(|| self.matchers[i].is_match(text))
+        })
+    }
+
+    fn eval(&self, node: &Node, found: &mut dyn FnMut(usize) -> bool) -> bool {
+        match node {
+            Node::Term(i) => found(*i),
+            Node::Not(inner) => !self.eval(inner, found),
+            Node::And(items) => items.iter().all(|item| self.eval(item, found)),
+            Node::Or(items) => items.iter().any(|item| self.eval(item, found)),
+        }
+    }
+
+    /// a pattern for the lines that may match: when the expression needs at least
+    /// one of its terms, the terms not under a `NOT`, otherwise every line
+    pub fn pattern(&self) -> Regex {
+        if self.eval(&self.root, &mut |_| false) {
+            return Regex::new("^.*$", false).unwrap();
+        }
+        let mut wanted = Vec::new();
+        positive(&self.root, false, &mut wanted);
+        let texts: Vec<&str> = wanted.iter().map(|&i| self.terms[i].as_str()).collect();
+        Regex::any_literal(&texts, self.ignore_case)
+    }
+}
+
+// the terms of `node` that make it more likely to hold, rather than less
+fn positive(node: &Node, negated: bool, terms: &mut Vec<usize>) {
+    match node {
+        Node::Term(i) if !negated && !terms.contains(i) => terms.push(*i),
+        Node::Term(_) => {}
+        Node::Not(inner) => positive(inner, !negated, terms),
+        Node::And(items) | Node::Or(items) => {
+            items.iter().for_each(|item| positive(item, negated, terms))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(query: &str) -> Plan {
+        Plan::compile(query, false).unwrap()
+    }
+
+    fn error(query: &str) -> (usize, &'static str) {
+        let e = Plan::compile(query, false).err().unwrap();
+        (e.pos, e.msg)
+    }
+
+    #[test]
+    fn operators() {
+        let q = plan("error AND timeout AND NOT retry");
+        assert!(q.matches("error: timeout after 3s"));
+        assert!(!q.matches("error: timeout, will retry"));
+        assert!(!q.matches("timeout"));
+
+        // AND binds tighter than OR, and words next to each other are joined by it
+        let q = plan("panic OR \"fatal error\" disk");
+        assert!(q.matches("panic"));
+        assert!(q.matches("disk: fatal error"));
+        assert!(!q.matches("fatal disk error"));
+
+        let q = plan("(a OR b) NOT (c d)");
+        assert!(q.matches("a c"));
+        assert!(!q.matches("b c d"));
+        assert!(plan("\"say \\\"hi\\\"\"").matches("they say \"hi\""));
+        // only capitals are operators
+        assert!(plan("rock and roll").matches("rock and roll"));
+    }
+
+    #[test]
+    fn plans() {
+        let q = plan("(x AND y) OR NOT NOT x");
+        // `x` is one term, and double negation is gone
+        assert_eq!(2, q.terms.len());
+        assert_eq!(
+            Node::Or(vec![
+                Node::Term(0),
+                Node::And(vec![Node::Term(0), Node::Term(1)])
+            ]),
+            q.root
+        );
+
+        let lines = |q: &Plan| {
+            ["error retry", "error", "ok"]
+                .iter()
+                .filter(|line| q.pattern().is_match(line))
+                .count()
+        };
+        // lines without "error" can't match
+        assert_eq!(2, lines(&plan("error NOT retry")));
+        // but any line without "retry" can
+        assert_eq!(3, lines(&plan("NOT retry")));
+
+        assert!(Plan::compile("ERROR", true).unwrap().matches("error"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!((10, "expected a term"), error("error AND "));
+        assert_eq!((0, "expected a term before the operator"), error("OR b"));
+        assert_eq!((2, "unclosed '('"), error("a (b OR c"));
+        assert_eq!((2, "unmatched ')'"), error("a ) b"));
+        assert_eq!((6, "unclosed '\"'"), error("a AND \"b c"));
+        assert_eq!((0, "empty phrase"), error("\"\""));
+        assert_eq!((1, "expected a term before ')'"), error("()"));
+    }
+}
@@ -0,0 +1,899 @@
+//! a small regular expression engine for minigrep patterns
+//!
+//! supports literals, `.`, classes like `[a-z_]` and `[^0-9]`, `\d \w \s` (and their
+//! negations), `^ $ \b \B`, groups `( )` and `(?: )`, alternation and the usual
+//! `* + ? {n} {n,} {n,m}` repetitions with lazy `?` variants.
+//!
+//! patterns are compiled to a small program and run on a pike VM, so matching time is
+//! linear in the text no matter how much the pattern would backtrack
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub msg: &'static str,
+    // character offset into the pattern
+    pub pos: usize,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid pattern at position {}: {}", self.pos, self.msg)
+    }
+}
+
+impl Error for RegexError {}
+
+// big counted repetitions are expanded into copies of the program
+const MAX_REPEAT: u32 = 1000;
+
+// and repetitions inside repetitions multiply, so the program has a limit too
+const MAX_PROG: usize = 100_000;
+
+#[derive(Debug, Clone)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn new(ranges: &[(char, char)], negated: bool) -> Class {
+        Class {
+            ranges: ranges.to_vec(),
+            negated,
+        }
+    }
+
+    fn contains(&self, c: char, ignore_case: bool) -> bool {
+        let hit = |c: char| self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+        let found = hit(c) || (ignore_case && (hit(lower(c)) || hit(upper(c))));
+        found != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &'static str) -> RegexError {
+        RegexError { msg, pos: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alt(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_repeat(atom)?);
+        }
+
+        match items.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(items.pop().unwrap()),
+            _ => Ok(Node::Concat(items)),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+
+        match c {
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(self.error("unknown group flag"));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.parse_alt()?;
+                if !self.eat(')') {
+                    return Err(self.error("missing ')'"));
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Look(Look::LineStart)),
+            '$' => Ok(Node::Look(Look::LineEnd)),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                Err(self.error("nothing to repeat"))
+            }
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or_else(|| self.error("trailing '\\'"))?;
+        self.pos += 1;
+
+        let node = match c {
+            'd' => Node::Class(Class::new(DIGIT, false)),
+            'D' => Node::Class(Class::new(DIGIT, true)),
+            'w' => Node::Class(Class::new(WORD, false)),
+            'W' => Node::Class(Class::new(WORD, true)),
+            's' => Node::Class(Class::new(SPACE, false)),
+            'S' => Node::Class(Class::new(SPACE, true)),
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            'r' => Node::Char('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error("unknown escape"));
+            }
+            c => Node::Char(c),
+        };
+        Ok(node)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                let e = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+                self.pos += 1;
+                match e {
+                    'd' => {
+                        ranges.extend_from_slice(DIGIT);
+                        continue;
+                    }
+                    'w' => {
+                        ranges.extend_from_slice(WORD);
+                        continue;
+                    }
+                    's' => {
+                        ranges.extend_from_slice(SPACE);
+                        continue;
+                    }
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    e if e.is_ascii_alphanumeric() => {
+                        self.pos -= 1;
+                        return Err(self.error("unknown escape in class"));
+                    }
+                    e => e,
+                }
+            } else {
+                c
+            };
+
+            // a '-' right before ']' is a literal dash
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                let mut hi = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+                self.pos += 1;
+                if hi == '\\' {
+                    hi = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+                    self.pos += 1;
+                }
+                if hi < lo {
+                    return Err(self.error("class range out of order"));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+
+        Ok(Node::Class(Class { ranges, negated }))
+    }
+
+    fn parse_repeat(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            if let Node::Look(_) | Node::Empty = node {
+                self.pos = start;
+                return Err(self.error("nothing to repeat"));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // `{n}`, `{n,}` or `{n,m}`; anything else leaves `{` as a literal
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        let end = match self.chars[start..].iter().position(|&c| c == '}') {
+            Some(end) => start + end,
+            None => return Ok(None),
+        };
+        let body: String = self.chars[start + 1..end].iter().collect();
+        let number = |s: &str| s.parse::<u32>().ok();
+
+        let counts = match body.find(',') {
+            None => number(&body).map(|n| (n, Some(n))),
+            Some(comma) if comma + 1 == body.len() => number(&body[..comma]).map(|n| (n, None)),
+            Some(comma) => match (number(&body[..comma]), number(&body[comma + 1..])) {
+                (Some(lo), Some(hi)) => Some((lo, Some(hi))),
+                _ => None,
+            },
+        };
+
+        match counts {
+            None => Ok(None),
+            Some((min, max)) => {
+                if max.is_some_and(|max| max < min) {
+                    return Err(self.error("repetition range out of order"));
+                }
+                if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+                    return Err(self.error("repetition count too large"));
+                }
+                self.pos = end + 1;
+                Ok(Some((min, max)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+    // instructions the program may grow to
+    limit: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    fn patch(&mut self, at: usize, preferred: usize, other: usize, greedy: bool) {
+        self.prog[at] = if greedy {
+            Inst::Split(preferred, other)
+        } else {
+            Inst::Split(other, preferred)
+        };
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        // checked before every node, so a program only gets a few instructions
+        // past the limit before this stops it
+        if self.prog.len() > self.limit {
+            return Err(RegexError {
+                msg: "pattern too large once its repetitions are expanded",
+                pos: 0,
+            });
+        }
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.emit(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()));
+            }
+            Node::Look(look) => {
+                self.emit(Inst::Look(*look));
+            }
+            Node::Group(inner, None) => self.compile(inner)?,
+            Node::Group(inner, Some(index)) => {
+                self.emit(Inst::Save(index * 2));
+                self.compile(inner)?;
+                self.emit(Inst::Save(index * 2 + 1));
+            }
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Node::Alt(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 == branches.len() {
+                        self.compile(branch)?;
+                    } else {
+                        let split = self.emit(Inst::Match);
+                        self.compile(branch)?;
+                        jumps.push(self.emit(Inst::Match));
+                        let next = self.prog.len();
+                        self.patch(split, split + 1, next, true);
+                    }
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Match);
+                        self.compile(node)?;
+                        self.emit(Inst::Jmp(split));
+                        let end = self.prog.len();
+                        self.patch(split, split + 1, end, *greedy);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Match));
+                            self.compile(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.patch(split, split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    groups: usize,
+    ignore_case: bool,
+    // the whole pattern is this case-sensitive string, so `str::find` will do
+    literal: Option<String>,
+    // every match starts with this string
+    prefix: String,
+    // every match contains this string, so text without it can be skipped
+    required: Option<String>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched ')'"));
+        }
+
+        Regex::compile(&node, parser.groups, ignore_case, MAX_PROG)
+    }
+
+    /// a pattern that matches `text` exactly, without treating any character as special
+    pub fn literal(text: &str, ignore_case: bool) -> Regex {
+        let node = Node::Concat(text.chars().map(Node::Char).collect());
+        // as long as the text itself, no need for a limit
+        Regex::compile(&node, 0, ignore_case, usize::MAX).unwrap()
+    }
+
+    /// a pattern that matches any of `texts` exactly
+    pub fn any_literal(texts: &[&str], ignore_case: bool) -> Regex {
+        let branches = texts
+            .iter()
+            .map(|text| Node::Concat(text.chars().map(Node::Char).collect()))
+            .collect();
+        Regex::compile(&Node::Alt(branches), 0, ignore_case, usize::MAX).unwrap()
+    }
+
+    fn compile(
+        node: &Node,
+        groups: usize,
+        ignore_case: bool,
+        limit: usize,
+    ) -> Result<Regex, RegexError> {
+        let mut compiler = Compiler {
+            prog: Vec::new(),
+            limit,
+        };
+        compiler.emit(Inst::Save(0));
+        compiler.compile(node)?;
+        compiler.emit(Inst::Save(1));
+        compiler.emit(Inst::Match);
+        let prog = compiler.prog;
+
+        let mut prefix = String::new();
+        if !ignore_case {
+            for inst in &prog[1..] {
+                match inst {
+                    Inst::Char(c) => prefix.push(*c),
+                    _ => break,
+                }
+            }
+        }
+        let literal = if prog.len() == prefix.chars().count() + 3 {
+            Some(prefix.clone())
+        } else {
+            None
+        };
+
+        let required = if ignore_case {
+            None
+        } else {
+            required_literal(node)
+        };
+
+        Ok(Regex {
+            prog,
+            groups,
+            ignore_case,
+            literal,
+            prefix,
+            required,
+        })
+    }
+
+    /// number of capture groups, not counting the whole match
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        if let Some(literal) = &self.literal {
+            return text.contains(literal.as_str());
+        }
+        self.exec::<usize>(text, 0, true).is_some()
+    }
+
+    /// byte range of the leftmost match starting at or after `start`
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        if let Some(literal) = &self.literal {
+            return text[start..]
+                .find(literal.as_str())
+                .map(|i| (start + i, start + i + literal.len()));
+        }
+        self.exec::<usize>(text, start, false)
+    }
+
+    /// like `find_at`, but also returns the range of every capture group;
+    /// index 0 is the whole match
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let (slots, _) = self.exec::<Vec<Option<usize>>>(text, start, false)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| match (pair[0], pair[1]) {
+                    (Some(s), Some(e)) => Some((s, e)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// every non-overlapping match, left to right
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches {
+            re: self,
+            text,
+            pos: 0,
+        }
+    }
+
+    // run the VM from `start`. with `earliest` any match will do, so it stops at
+    // the first one seen instead of finishing the leftmost-first match.
+    // returns the slots of the winning thread and where its match ends
+    fn exec<S: Slots>(&self, text: &str, start: usize, earliest: bool) -> Option<(S, usize)> {
+        if let Some(required) = &self.required {
+            if !text[start..].contains(required.as_str()) {
+                return None;
+            }
+        }
+
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            if matched.is_none() {
+                // nothing alive: skip straight to the next place the prefix occurs
+                if clist.threads.is_empty() && !self.prefix.is_empty() {
+                    pos += text[pos..].find(self.prefix.as_str())?;
+                }
+                let slots = S::new(self.groups, pos);
+                self.add(&mut clist, 0, pos, slots, text);
+            }
+            if clist.threads.is_empty() && (matched.is_some() || pos >= text.len()) {
+                break;
+            }
+
+            let next = text[pos..].chars().next();
+            let threads = std::mem::take(&mut clist.threads);
+            for (pc, slots) in threads {
+                let step = match (&self.prog[pc], next) {
+                    (Inst::Match, _) => {
+                        if earliest {
+                            return Some((slots, pos));
+                        }
+                        // lower priority threads can't win any more
+                        matched = Some((slots, pos));
+                        break;
+                    }
+                    (Inst::Char(want), Some(c)) => {
+                        *want == c || (self.ignore_case && lower(*want) == lower(c))
+                    }
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.contains(c, self.ignore_case),
+                    _ => false,
+                };
+                if step {
+                    let width = next.map_or(0, char::len_utf8);
+                    self.add(&mut nlist, pc + 1, pos + width, slots, text);
+                }
+            }
+
+            match next {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            // `seen` carries over, so a fresh start thread can't displace the ones already here
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+
+        matched
+    }
+
+    // follow jumps, splits, saves and assertions so the list only holds
+    // instructions that consume a character (or match)
+    fn add<S: Slots>(
+        &self,
+        list: &mut Threads<S>,
+        pc: usize,
+        pos: usize,
+        mut slots: S,
+        text: &str,
+    ) {
+        if list.seen[pc] {
+            return;
+        }
+        list.seen[pc] = true;
+
+        match &self.prog[pc] {
+            Inst::Jmp(to) => self.add(list, *to, pos, slots, text),
+            Inst::Split(a, b) => {
+                self.add(list, *a, pos, slots.clone(), text);
+                self.add(list, *b, pos, slots, text);
+            }
+            Inst::Save(slot) => {
+                slots.save(*slot, pos);
+                self.add(list, pc + 1, pos, slots, text);
+            }
+            Inst::Look(look) => {
+                if looks(*look, text, pos) {
+                    self.add(list, pc + 1, pos, slots, text);
+                }
+            }
+            _ => list.threads.push((pc, slots)),
+        }
+    }
+}
+
+// what a thread remembers about where it has been
+trait Slots: Clone {
+    fn new(groups: usize, pos: usize) -> Self;
+    fn save(&mut self, slot: usize, pos: usize);
+}
+
+// every capture group, for `captures_at`
+impl Slots for Vec<Option<usize>> {
+    fn new(groups: usize, _pos: usize) -> Self {
+        vec![None; (groups + 1) * 2]
+    }
+
+    fn save(&mut self, slot: usize, pos: usize) {
+        self[slot] = Some(pos);
+    }
+}
+
+// just where the match started, which is all `find_at` needs and much cheaper to copy
+impl Slots for usize {
+    fn new(_groups: usize, pos: usize) -> Self {
+        pos
+    }
+
+    fn save(&mut self, _slot: usize, _pos: usize) {}
+}
+
+// the longest run of plain characters that every match has to go through
+fn required_literal(node: &Node) -> Option<String> {
+    let items = match node {
+        Node::Concat(items) => items.as_slice(),
+        Node::Char(_) => std::slice::from_ref(node),
+        _ => return None,
+    };
+
+    let mut best = String::new();
+    let mut run = String::new();
+    for item in items {
+        match item {
+            Node::Char(c) => run.push(*c),
+            _ => run.clear(),
+        }
+        if run.len() > best.len() {
+            best = run.clone();
+        }
+    }
+
+    if best.is_empty() {
+        None
+    } else {
+        Some(best)
+    }
+}
+
+struct Threads<S> {
+    threads: Vec<(usize, S)>,
+    seen: Vec<bool>,
+}
+
+impl<S> Threads<S> {
+    fn new(len: usize) -> Threads<S> {
+        Threads {
+            threads: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.seen.iter_mut().for_each(|seen| *seen = false);
+    }
+}
+
+fn looks(look: Look, text: &str, pos: usize) -> bool {
+    let before = text[..pos].chars().next_back();
+    let after = text[pos..].chars().next();
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+    match look {
+        Look::LineStart => before.is_none_or(|c| c == '\n'),
+        Look::LineEnd => after.is_none_or(|c| c == '\n') || text[pos..].starts_with("\r\n"),
+        Look::WordBoundary => is_word(before) != is_word(after),
+        Look::NotWordBoundary => is_word(before) == is_word(after),
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn upper(c: char) -> char {
+    c.to_uppercase().next().unwrap_or(c)
+}
+
+pub struct Matches<'r, 't> {
+    re: &'r Regex,
+    text: &'t str,
+    pos: usize,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.pos > self.text.len() {
+            return None;
+        }
+        let (start, end) = self.re.find_at(self.text, self.pos)?;
+
+        // step over empty matches so the iterator always makes progress
+        self.pos = if end == start {
+            end + self.text[end..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            end
+        };
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern, false).unwrap().find_at(text, 0)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert_eq!(Some((7, 11)), find("tape", "Duct. (tape)"));
+        assert_eq!(Some((4, 7)), find("[0-9]+", "abc 404 x"));
+        assert_eq!(Some((0, 7)), find(r"\w+_id", "user_id = 3"));
+        assert_eq!(Some((2, 3)), find("[^a-z]", "ab-c"));
+        assert_eq!(None, find(r"\d", "no digits"));
+    }
+
+    #[test]
+    fn repetition_is_leftmost_first() {
+        assert_eq!(Some((0, 5)), find("a.*b", "axbxb"));
+        assert_eq!(Some((0, 3)), find("a.*?b", "axbxb"));
+        assert_eq!(Some((0, 2)), find("x{2}", "xxx"));
+        assert_eq!(Some((0, 3)), find("x{1,3}", "xxxx"));
+        assert_eq!(Some((0, 4)), find("a{,}", "a{,}"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        let re = Regex::new(r"(\w+)@(\w+)\.com|none", false).unwrap();
+        let caps = re.captures_at("mail: ferris@rust.com", 0).unwrap();
+
+        assert_eq!(2, re.groups());
+        assert_eq!(Some((6, 21)), caps[0]);
+        assert_eq!(Some((6, 12)), caps[1]);
+        assert_eq!(Some((13, 17)), caps[2]);
+        assert_eq!(Some((0, 4)), re.find_at("none", 0));
+    }
+
+    #[test]
+    fn anchors_and_boundaries() {
+        let text = "fn main\n#[test]\nfn it_works";
+
+        assert_eq!(Some((16, 21)), find(r"^fn it", text));
+        assert_eq!(Some((0, 7)), find(r"^fn \w+$", text));
+        assert_eq!(None, find(r"\bain", text));
+        assert_eq!(Some((4, 7)), find(r"\Bain", text));
+    }
+
+    #[test]
+    fn matches_across_lines() {
+        let text = "#[test]\n    fn works() {}";
+
+        assert_eq!(Some((0, 14)), find(r"#\[test\]\s*fn", text));
+        assert_eq!(None, find(r"#\[test\].*fn", text));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let re = Regex::new("rU[s]t", true).unwrap();
+        assert_eq!(Some((1, 5)), re.find_at("Trust", 0));
+
+        let re = Regex::literal("DUCT", true);
+        assert_eq!(Some((0, 4)), re.find_at("Duct tape", 0));
+    }
+
+    #[test]
+    fn literal_does_not_interpret() {
+        let re = Regex::literal("a.b(", false);
+        assert_eq!(None, re.find_at("axb(", 0));
+        assert_eq!(Some((1, 5)), re.find_at("_a.b(", 0));
+    }
+
+    #[test]
+    fn find_iter_steps_over_empty_matches() {
+        let re = Regex::new("x*", false).unwrap();
+        let found: Vec<_> = re.find_iter("axxb").collect();
+
+        assert_eq!(vec![(0, 0), (1, 3), (3, 3), (4, 4)], found);
+    }
+
+    #[test]
+    fn errors_point_at_position() {
+        let err = Regex::new("ab(c", false).unwrap_err();
+        assert_eq!(4, err.pos);
+
+        let err = Regex::new("a)b", false).unwrap_err();
+        assert_eq!(1, err.pos);
+
+        let err = Regex::new("*a", false).unwrap_err();
+        assert_eq!("nothing to repeat", err.msg);
+
+        // each count is allowed, but together they'd need 10^9 instructions
+        assert!(Regex::new("a{1000}", false).is_ok());
+        let err = Regex::new("((a{1000}){1000}){1000}", false).unwrap_err();
+        assert_eq!(
+            "pattern too large once its repetitions are expanded",
+            err.msg
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! `--stdio-rpc`: keep one minigrep running and drive it with JSON-RPC 2.0
+//!
+//! every message is one line of JSON on stdin or stdout. the methods are
+//!
+//! - `search` with `{"query": "..", "paths": [".."], "ignore_case": false, "regex": false}`.
+//!   while it runs, each matching line is sent as a `result` notification carrying
+//!   the search's id, then the response gives the totals
+//! - `cancel` with `{"id": <id of a search>}`, answers whether that search was still running
+//! - `shutdown` stops every running search and ends the process. when stdin is
+//!   closed instead, the running searches are left to finish first
+//!
+//! searches run on their own threads, so a `cancel` is read while they're still going.
+//! a request without an id is a notification and is never answered, a `search`
+//! sent that way isn't run at all
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::cancel::Cancel;
+use crate::json::Json;
+use crate::regex::Regex;
+use crate::walk::{self, Filter};
+
+// the error codes from the JSON-RPC spec
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// stdout is shared by all searches, a message is written and flushed in one go
+struct Output<W: Write>(Mutex<W>);
+
+impl<W: Write> Output<W> {
+    fn send(&self, message: Json) {
+        let mut out = self.0.lock().unwrap();
+        // nobody left to read it isn't worth stopping for
+        let _ = writeln!(out, "{}", message).and_then(|_| out.flush());
+    }
+
+    fn respond(&self, id: &Json, result: Json) {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            ("result", result),
+        ]));
+    }
+
+    fn error(&self, id: &Json, code: i32, msg: &str) {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            (
+                "error",
+                Json::object(vec![
+                    ("code", Json::Number(code as f64)),
+                    ("message", msg.into()),
+                ]),
+            ),
+        ]));
+    }
+}
+
+// the searches still running, by the serialized form of their id
+type Running = Arc<Mutex<HashMap<String, Arc<Cancel>>>>;
+
+/// answer requests from `input` until it ends or a `shutdown` comes in
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write + Send + 'static,
+{
+    let output = Arc::new(Output(Mutex::new(output)));
+    let running: Running = Arc::new(Mutex::new(HashMap::new()));
+    let mut workers: Vec<JoinHandle<()>> = Vec::new();
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match Json::parse(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                output.error(&Json::Null, PARSE_ERROR, &e.to_string());
+                continue;
+            }
+        };
+        // requests without an id are notifications and get no answer
+        let id = request.get("id").cloned();
+        let method = match request.get("method").and_then(Json::as_str) {
+            Some(method) => method,
+            None => {
+                output.error(&id.unwrap_or(Json::Null), INVALID_REQUEST, "missing method");
+                continue;
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Json::Null);
+
+        match method {
+            "search" => {
+                // a notification gets nothing back, not even its results, and
+                // can't be cancelled, so there's no point running it
+                let id = match id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let search = match Search::new(&params) {
+                    Ok(search) => search,
+                    Err(msg) => {
+                        output.error(&id, INVALID_PARAMS, &msg);
+                        continue;
+                    }
+                };
+                let key = id.to_string();
+                let cancel = Arc::new(Cancel::new());
+                if running.lock().unwrap().contains_key(&key) {
+                    output.error(&id, INVALID_REQUEST, "a search with this id is running");
+                    continue;
+                }
+                running
+                    .lock()
+                    .unwrap()
+                    .insert(key.clone(), Arc::clone(&cancel));
+
+                let output = Arc::clone(&output);
+                let running = Arc::clone(&running);
+                workers.retain(|worker| !worker.is_finished());
+                workers.push(thread::spawn(move || {
+                    let result = search.run(&id, &cancel, &output);
+                    running.lock().unwrap().remove(&key);
+                    output.respond(&id, result);
+                }));
+            }
+            "cancel" => {
+                let target = params.get("id").map(Json::to_string);
+                let cancel = target.and_then(|key| running.lock().unwrap().get(&key).cloned());
+                if let Some(cancel) = &cancel {
+                    cancel.cancel();
+                }
+                if let Some(id) = &id {
+                    output.respond(id, cancel.is_some().into());
+                }
+            }
+            "shutdown" => {
+                stop(&running, workers);
+                if let Some(id) = &id {
+                    output.respond(id, Json::Null);
+                }
+                return Ok(());
+            }
+            _ => {
+                if let Some(id) = &id {
+                    output.error(
+                        id,
+                        METHOD_NOT_FOUND,
+                        &format!("unknown method '{}'", method),
+                    );
+                }
+            }
+        }
+    }
+
+    // stdin is closed, but searches already asked for still get their answers
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+// cancel every search and wait for its final response to be written
+fn stop(running: &Running, workers: Vec<JoinHandle<()>>) {
+    for cancel in running.lock().unwrap().values() {
+        cancel.cancel();
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+struct Search {
+    pattern: Regex,
+    paths: Vec<String>,
+}
+
+impl Search {
+    fn new(params: &Json) -> Result<Search, String> {
+        let query = params
+            .get("query")
+            .and_then(Json::as_str)
+            .ok_or("missing string parameter 'query'")?;
+        let flag = |name| params.get(name).and_then(Json::as_bool).unwrap_or(false);
+        let pattern = if flag("regex") {
+            Regex::new(query, flag("ignore_case")).map_err(|e| e.to_string())?
+        } else {
+            Regex::literal(query, flag("ignore_case"))
+        };
+
+        let paths = match params.get("paths") {
+            None => vec![String::from(".")],
+            Some(Json::Array(paths)) => paths
+                .iter()
+                .map(|path| path.as_str().map(String::from))
+                .collect::<Option<_>>()
+                .ok_or("'paths' must be a list of strings")?,
+            Some(_) => return Err(String::from("'paths' must be a list of strings")),
+        };
+        Ok(Search { pattern, paths })
+    }
+
+    // send every match as a notification and return the totals
+    fn run<W: Write>(&self, id: &Json, cancel: &Cancel, output: &Output<W>) -> Json {
+        let mut matches = 0;
+        let mut files = 0;
+        let mut errors = Vec::new();
+
+        for entry in walk::files(&self.paths, &Filter::default()) {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let file = match entry {
+                Ok(file) => file,
+                Err(e) => {
+                    errors.push(Json::from(e.to_string()));
+                    continue;
+                }
+            };
+            let contents = match crate::read_contents(&file, false) {
+                Ok((_, contents)) => contents,
+                Err(e) => {
+                    errors.push(Json::from(format!("{}: {}", file.display(), e)));
+                    continue;
+                }
+            };
+
+            files += 1;
+            let path = file.to_string_lossy();
+            for (line, text) in
+                crate::search_until(&self.pattern, &contents, false, usize::MAX, cancel)
+            {
+                matches += 1;
+                output.send(Json::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("method", "result".into()),
+                    (
+                        "params",
+                        Json::object(vec![
+                            ("id", id.clone()),
+                            ("path", path.as_ref().into()),
+                            ("line", line.into()),
+                            ("text", text.into()),
+                        ]),
+                    ),
+                ]));
+            }
+        }
+
+        Json::object(vec![
+            ("matches", matches.into()),
+            ("files", files.into()),
+            ("cancelled", cancel.is_cancelled().into()),
+            ("errors", Json::Array(errors)),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a Write that the test can still read after handing it over
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn messages(input: &str) -> Vec<Json> {
+        let output = Shared::default();
+        serve(input.as_bytes(), output.clone()).unwrap();
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .map(|line| Json::parse(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn search_streams_results() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let input = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"search","params":{{"query":"EDITION","ignore_case":true,"paths":["{}/Cargo.toml"]}}}}"#,
+            dir
+        );
+        let messages = messages(&input);
+
+        assert_eq!(2, messages.len());
+        let params = messages[0].get("params").unwrap();
+        assert_eq!(
+            Some("result"),
+            messages[0].get("method").and_then(Json::as_str)
+        );
+        assert_eq!(Some(&Json::Number(1.0)), params.get("id"));
+        assert_eq!(Some(4.0), params.get("line").and_then(Json::as_f64));
+
+        let result = messages[1].get("result").unwrap();
+        assert_eq!(Some(1.0), result.get("matches").and_then(Json::as_f64));
+        assert_eq!(Some(false), result.get("cancelled").and_then(Json::as_bool));
+    }
+
+    #[test]
+    fn errors_and_shutdown() {
+        let messages = messages(
+            "{\"id\":1,\"method\":\"search\",\"params\":{}}\n\
+             {\"method\":\"search\",\"params\":{}}\n\
+             {\"method\":\"search\",\"params\":{\"query\":\"fn\"}}\n\
+             {\"method\":\"replace\"}\n\
+             not json\n\
+             {\"id\":2,\"method\":\"replace\"}\n\
+             {\"id\":3,\"method\":\"cancel\",\"params\":{\"id\":42}}\n\
+             {\"id\":4,\"method\":\"shutdown\"}\n\
+             {\"id\":5,\"method\":\"cancel\",\"params\":{\"id\":42}}\n",
+        );
+        let code = |m: &Json| {
+            m.get("error")
+                .and_then(|e| e.get("code"))
+                .and_then(Json::as_f64)
+        };
+
+        // notifications, good or bad, get no answer
+        assert_eq!(5, messages.len());
+        assert_eq!(Some(INVALID_PARAMS as f64), code(&messages[0]));
+        assert_eq!(Some(PARSE_ERROR as f64), code(&messages[1]));
+        assert_eq!(Some(METHOD_NOT_FOUND as f64), code(&messages[2]));
+        // nothing with id 42 is running
+        assert_eq!(Some(&Json::Bool(false)), messages[3].get("result"));
+        // nothing is read after the shutdown
+        assert_eq!(Some(&Json::Number(4.0)), messages[4].get("id"));
+    }
+
+    #[test]
+    fn cancel_stops_a_running_search() {
+        let output = Shared::default();
+        let rpc = Output(Mutex::new(output.clone()));
+        let cancel = Cancel::new();
+        cancel.cancel();
+        let search = Search::new(&Json::object(vec![
+            ("query", "fn".into()),
+            (
+                "paths",
+                Json::Array(vec![env!("CARGO_MANIFEST_DIR").into()]),
+            ),
+        ]))
+        .unwrap();
+
+        let result = search.run(&Json::Number(1.0), &cancel, &rpc);
+        assert_eq!(Some(true), result.get("cancelled").and_then(Json::as_bool));
+        assert_eq!(Some(0.0), result.get("matches").and_then(Json::as_f64));
+    }
+}
@@ -0,0 +1,295 @@
+//! `--scope`: only count matches inside the code, the comments or the string
+//! literals of a Rust file
+//!
+//! the lexer knows just enough Rust to split a file into those regions: line and
+//! doc comments, nested block comments, (raw) string, byte string and char literals,
+//! and lifetimes, which look like the start of a char literal but aren't one
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Code,
+    Comments,
+    // string and char literals
+    Strings,
+}
+
+impl Scope {
+    pub fn parse(text: &str) -> Result<Scope, String> {
+        match text {
+            "code" => Ok(Scope::Code),
+            "comments" => Ok(Scope::Comments),
+            "strings" => Ok(Scope::Strings),
+            _ => Err(format!(
+                "unknown scope '{}', use code, comments or strings",
+                text
+            )),
+        }
+    }
+}
+
+/// a file cut into consecutive regions, each with its scope
+#[derive(Debug, PartialEq)]
+pub struct Regions {
+    // (start, end, scope) byte ranges, sorted and covering the whole text
+    spans: Vec<(usize, usize, Scope)>,
+}
+
+impl Regions {
+    /// whether the byte range `start..end` lies inside one region of `scope`
+    pub fn contains(&self, start: usize, end: usize, scope: Scope) -> bool {
+        let index = self.spans.partition_point(|&(_, e, _)| e <= start);
+        match self.spans.get(index) {
+            Some(&(s, e, kind)) => kind == scope && s <= start && end <= e,
+            None => false,
+        }
+    }
+}
+
+/// split Rust source into code, comment and literal regions
+pub fn lex(text: &str) -> Regions {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    let mut code_start = 0;
+
+    while i < bytes.len() {
+        let (kind, end) = match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => (Scope::Comments, line_end(bytes, i)),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                (Scope::Comments, block_comment_end(bytes, i))
+            }
+            b'"' => (Scope::Strings, string_end(bytes, i + 1)),
+            b'\'' => match char_end(text, i + 1) {
+                Some(end) => (Scope::Strings, end),
+                // a lifetime or a label
+                None => {
+                    i += 1;
+                    continue;
+                }
+            },
+            c if is_ident_start(c) => {
+                let after_ident = i > 0 && is_ident(bytes[i - 1]);
+                match literal_with_prefix(text, i) {
+                    Some(end) if !after_ident => (Scope::Strings, end),
+                    _ => {
+                        i += 1;
+                        while i < bytes.len() && is_ident(bytes[i]) {
+                            i += 1;
+                        }
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        push(&mut spans, code_start, i, Scope::Code);
+        push(&mut spans, i, end, kind);
+        i = end;
+        code_start = end;
+    }
+    push(&mut spans, code_start, bytes.len(), Scope::Code);
+
+    Regions { spans }
+}
+
+// add a region, merging it into the previous one when the scope is the same
+fn push(spans: &mut Vec<(usize, usize, Scope)>, start: usize, end: usize, scope: Scope) {
+    if start == end {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.2 == scope && last.1 == start => last.1 = end,
+        _ => spans.push((start, end, scope)),
+    }
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+// non-ASCII bytes are treated as identifier characters too
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+fn line_end(bytes: &[u8], from: usize) -> usize {
+    bytes[from..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| from + i)
+}
+
+// block comments nest in Rust: `/* a /* b */ still comment */`
+fn block_comment_end(bytes: &[u8], from: usize) -> usize {
+    let mut depth = 0;
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+// `from` is just past the opening quote
+fn string_end(bytes: &[u8], from: usize) -> usize {
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+// `from` is just past the opening quote. `None` when this is a lifetime instead
+fn char_end(text: &str, from: usize) -> Option<usize> {
+    let rest = &text[from..];
+    if rest.starts_with('\\') {
+        // '\n', '\'', '\u{1F980}'
+        let close = rest.get(2..)?.find('\'')?;
+        return Some(from + 2 + close + 1);
+    }
+    let c = rest.chars().next()?;
+    if rest[c.len_utf8()..].starts_with('\'') {
+        Some(from + c.len_utf8() + 1)
+    } else {
+        None
+    }
+}
+
+// string and char literals that start with letters: b"..", b'.', c"..",
+// r"..", r#".."#, br"..", cr#".."#
+fn literal_with_prefix(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = from;
+    if bytes[i] == b'b' || bytes[i] == b'c' {
+        i += 1;
+    }
+
+    if bytes.get(i) == Some(&b'r') {
+        let mut hashes = 0;
+        i += 1;
+        while bytes.get(i) == Some(&b'#') {
+            hashes += 1;
+            i += 1;
+        }
+        if bytes.get(i) != Some(&b'"') {
+            // a raw identifier like r#match, or just a name
+            return None;
+        }
+        let mut closing = vec![b'"'];
+        closing.resize(hashes + 1, b'#');
+        let end = bytes[i + 1..]
+            .windows(closing.len())
+            .position(|window| window == closing.as_slice())
+            .map_or(bytes.len(), |pos| i + 1 + pos + closing.len());
+        return Some(end);
+    }
+
+    if i == from {
+        return None;
+    }
+    match bytes.get(i) {
+        Some(b'"') => Some(string_end(bytes, i + 1)),
+        Some(b'\'') if bytes[from] == b'b' => char_end(text, i + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(text: &str, scope: Scope) -> Vec<&str> {
+        lex(text)
+            .spans
+            .iter()
+            .filter(|span| span.2 == scope)
+            .map(|&(s, e, _)| &text[s..e])
+            .collect()
+    }
+
+    #[test]
+    fn comments() {
+        let text = "\
+/// doc
+fn main() { // trailing
+    let x = 1; /* outer /* inner */ still */ let y = 2;
+}";
+        assert_eq!(
+            vec!["/// doc", "// trailing", "/* outer /* inner */ still */"],
+            regions(text, Scope::Comments)
+        );
+    }
+
+    #[test]
+    fn strings_and_chars() {
+        let text = r###"let a = "say \"hi\" // not a comment"; let b = r#"raw "quoted""#;
+let c = b"bytes"; let d = '"'; let e = '\''; let f = br"x";"###;
+
+        assert_eq!(
+            vec![
+                r#""say \"hi\" // not a comment""#,
+                r###"r#"raw "quoted""#"###,
+                r#"b"bytes""#,
+                r#"'"'"#,
+                r"'\''",
+                r#"br"x""#,
+            ],
+            regions(text, Scope::Strings)
+        );
+        assert!(regions(text, Scope::Comments).is_empty());
+    }
+
+    #[test]
+    fn lifetimes_are_code() {
+        let text = "fn longest<'a>(x: &'a str) -> &'a str { 'outer: loop { break 'outer; } }";
+
+        assert!(regions(text, Scope::Strings).is_empty());
+        assert_eq!(vec![text], regions(text, Scope::Code));
+    }
+
+    #[test]
+    fn identifiers_are_not_prefixes() {
+        let text = "let r#match = bar(\"s\"); let br = 1;";
+
+        assert_eq!(vec!["\"s\""], regions(text, Scope::Strings));
+    }
+
+    #[test]
+    fn contains() {
+        let text = "let s = \"panic\"; panic!(); // panic";
+        let lexed = lex(text);
+        let find_all: Vec<usize> = text.match_indices("panic").map(|(i, _)| i).collect();
+
+        let scopes: Vec<Scope> = find_all
+            .iter()
+            .map(|&i| {
+                [Scope::Code, Scope::Comments, Scope::Strings]
+                    .iter()
+                    .copied()
+                    .find(|&scope| lexed.contains(i, i + 5, scope))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(vec![Scope::Strings, Scope::Code, Scope::Comments], scopes);
+        // a range crossing from code into a comment belongs to neither
+        assert!(!lexed.contains(24, 32, Scope::Code));
+    }
+}
@@ -0,0 +1,471 @@
+//! `minigrep serve`: answer searches over HTTP
+//!
+//! `GET /search?q=needle&path=src&ignore_case=true&regex=false` searches `path`
+//! (relative to `--root`, the root itself when left out) and streams back
+//!
+//! ```text
+//! {"results":[{"path":"src/lib.rs","line":12,"text":"..."},...],"files":3,"errors":[]}
+//! ```
+//!
+//! results are sent with chunked transfer encoding one file at a time, so a big
+//! search never has to sit in memory. every request gets its own thread, up to
+//! `--max-requests` at once; past that the server answers 503 right away
+
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::Cancel;
+use crate::json::Json;
+use crate::regex::Regex;
+use crate::walk::{self, Filter};
+
+// longest request line or header accepted, and how many headers
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+pub struct ServeConfig {
+    // every searched path has to stay inside this directory
+    pub root: PathBuf,
+    pub port: u16,
+    // requests handled at the same time
+    pub max_requests: usize,
+}
+
+impl ServeConfig {
+    /// parse the arguments after `minigrep serve`
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<ServeConfig, String> {
+        let mut root = PathBuf::from(".");
+        let mut port = 8080;
+        let mut max_requests = 8;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("'{}' needs a value", arg))
+            };
+            match arg.as_str() {
+                "--root" => root = PathBuf::from(value()?),
+                "--port" => {
+                    let text = value()?;
+                    port = text
+                        .parse()
+                        .map_err(|_| format!("invalid port '{}'", text))?;
+                }
+                "--max-requests" => {
+                    let text = value()?;
+                    max_requests = text
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid number '{}'", text))?
+                        .max(1);
+                }
+                _ => return Err(format!("unknown option '{}' for serve", arg)),
+            }
+        }
+
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", root.display(), e))?;
+        Ok(ServeConfig {
+            root,
+            port,
+            max_requests,
+        })
+    }
+}
+
+/// listen on localhost and serve until the process is killed
+pub fn serve(config: ServeConfig) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", config.port))?;
+    eprintln!(
+        "serving {} on http://{}",
+        config.root.display(),
+        listener.local_addr()?
+    );
+    Ok(serve_on(listener, config)?)
+}
+
+pub fn serve_on(listener: TcpListener, config: ServeConfig) -> io::Result<()> {
+    let root = Arc::new(config.root);
+    let active = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        // a client that stops talking, or stops reading, shouldn't hold a slot
+        // forever. a connection that can't have that is dropped like a failed accept
+        let timeout = Some(Duration::from_secs(10));
+        if stream.set_read_timeout(timeout).is_err() || stream.set_write_timeout(timeout).is_err() {
+            continue;
+        }
+
+        let slot = match Slot::take(&active, config.max_requests) {
+            Some(slot) => slot,
+            None => {
+                // written here on the accepting thread, so a client that doesn't
+                // read it only gets a moment before the next one is accepted
+                let busy = Some(Duration::from_millis(100));
+                if stream.set_write_timeout(busy).is_ok() {
+                    let _ = respond_error(&mut stream, 503, "too many requests at once");
+                }
+                continue;
+            }
+        };
+        let root = Arc::clone(&root);
+        thread::spawn(move || {
+            let _slot = slot;
+            // the client hanging up early isn't the server's problem
+            let _ = handle(stream, &root);
+        });
+    }
+    Ok(())
+}
+
+// one of the `max_requests` places, given back when dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(active: &Arc<AtomicUsize>, max: usize) -> Option<Slot> {
+        if active.fetch_add(1, Ordering::SeqCst) >= max {
+            active.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Slot(Arc::clone(active)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle(mut stream: TcpStream, root: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    (&mut reader).take(MAX_LINE).read_line(&mut line)?;
+
+    // the headers aren't used, but they have to be read before answering
+    for _ in 0..MAX_HEADERS {
+        let mut header = String::new();
+        if (&mut reader).take(MAX_LINE).read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return respond_error(&mut stream, 400, "malformed request"),
+    };
+    if method != "GET" {
+        return respond_error(&mut stream, 405, "only GET is supported");
+    }
+    let (route, query) = target.split_once('?').unwrap_or((target, ""));
+    if route != "/search" {
+        return respond_error(&mut stream, 404, "not found, try /search?q=...");
+    }
+
+    let params = match parse_query(query) {
+        Ok(params) => params,
+        Err(msg) => return respond_error(&mut stream, 400, &msg),
+    };
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let flag = |name: &str| matches!(param(name), Some("true") | Some("1") | Some(""));
+
+    let query = match param("q") {
+        Some(q) if !q.is_empty() => q,
+        _ => return respond_error(&mut stream, 400, "missing query parameter 'q'"),
+    };
+    let pattern = if flag("regex") {
+        match Regex::new(query, flag("ignore_case")) {
+            Ok(pattern) => pattern,
+            Err(e) => return respond_error(&mut stream, 400, &e.to_string()),
+        }
+    } else {
+        Regex::literal(query, flag("ignore_case"))
+    };
+    let start = match resolve(root, param("path").unwrap_or("")) {
+        Ok(start) => start,
+        Err(msg) => return respond_error(&mut stream, 403, &msg),
+    };
+
+    stream_results(&mut stream, root, &start, &pattern)
+}
+
+// write the search out one file per chunk
+fn stream_results(
+    stream: &mut TcpStream,
+    root: &Path,
+    start: &Path,
+    pattern: &Regex,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+         Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut out = BufWriter::new(Chunked(stream));
+    write!(out, "{{\"results\":[")?;
+
+    let cancel = Cancel::new();
+    let mut first = true;
+    let mut files = 0;
+    let mut errors = Vec::new();
+    let start = start.to_string_lossy().into_owned();
+    // clients are only told paths relative to the root, not where it is
+    let relative = |path: &Path| match path.strip_prefix(root) {
+        Ok(inside) if inside == Path::new("") => String::from("."),
+        Ok(inside) => inside.to_string_lossy().into_owned(),
+        Err(_) => path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    for entry in walk::files(&[start], &Filter::default()) {
+        let file = match entry {
+            Ok(file) => file,
+            Err(e) => {
+                errors.push(Json::from(format!("{}: {}", relative(&e.path), e.err)));
+                continue;
+            }
+        };
+        let shown = relative(&file);
+        // a symlink inside the root may still point outside of it
+        if !file.canonicalize().is_ok_and(|real| real.starts_with(root)) {
+            errors.push(Json::from(format!("{}: outside of the root", shown)));
+            continue;
+        }
+        let contents = match crate::read_contents(&file, false) {
+            Ok((_, contents)) => contents,
+            Err(e) => {
+                errors.push(Json::from(format!("{}: {}", shown, e)));
+                continue;
+            }
+        };
+
+        files += 1;
+        for (line, text) in crate::search_until(pattern, &contents, false, usize::MAX, &cancel) {
+            if !first {
+                write!(out, ",")?;
+            }
+            first = false;
+            let result = Json::object(vec![
+                ("path", shown.as_str().into()),
+                ("line", line.into()),
+                ("text", text.into()),
+            ]);
+            write!(out, "{}", result)?;
+        }
+        out.flush()?;
+    }
+
+    write!(
+        out,
+        "],\"files\":{},\"errors\":{}}}",
+        files,
+        Json::Array(errors)
+    )?;
+    out.flush()?;
+    // the zero length chunk ends the body
+    out.get_mut().0.write_all(b"0\r\n\r\n")
+}
+
+// HTTP/1.1 chunked transfer encoding, every write is one chunk
+struct Chunked<'a>(&'a mut TcpStream);
+
+impl Write for Chunked<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.0, "{:x}\r\n", buf.len())?;
+        self.0.write_all(buf)?;
+        self.0.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn respond_error(stream: &mut TcpStream, status: u16, msg: &str) -> io::Result<()> {
+    let reason = match status {
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Service Unavailable",
+    };
+    let body = Json::object(vec![("error", msg.into())]).to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )
+}
+
+/// find `requested` under `root`, refusing anything that ends up outside of it,
+/// through `..` or a symlink
+fn resolve(root: &Path, requested: &str) -> Result<PathBuf, String> {
+    if Path::new(requested).is_absolute() {
+        return Err(format!("'{}' must be relative to the root", requested));
+    }
+    let full = root
+        .join(requested)
+        .canonicalize()
+        .map_err(|_| format!("'{}' doesn't exist", requested))?;
+    if !full.starts_with(root) {
+        return Err(format!("'{}' is outside of the root", requested));
+    }
+    Ok(full)
+}
+
+/// split `a=1&b=two` into decoded pairs
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+// `%41` is a byte and `+` a space, the result has to be UTF-8
+fn percent_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                // from_str_radix takes a sign as well, "%+1" isn't an escape
+                let byte = text
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("bad escape in '{}'", text))?;
+                out.push(byte);
+                i += 2;
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(out).map_err(|_| format!("'{}' isn't UTF-8", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn decode_query() {
+        assert_eq!(
+            Ok(vec![
+                (String::from("q"), String::from("fn main(")),
+                (String::from("ignore_case"), String::new()),
+                (String::from("path"), String::from("src/ü")),
+            ]),
+            parse_query("q=fn+main%28&ignore_case&path=src%2F%C3%BC")
+        );
+        assert!(parse_query("q=%zz").is_err());
+        assert!(parse_query("q=%ff").is_err());
+        assert!(parse_query("q=%+1").is_err());
+    }
+
+    #[test]
+    fn paths_stay_inside_the_root() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .canonicalize()
+            .unwrap();
+
+        assert_eq!(Ok(root.join("src")), resolve(&root, "src"));
+        assert_eq!(Ok(root.clone()), resolve(&root, ""));
+        assert_eq!(Ok(root.join("src")), resolve(&root, "tests/../src"));
+        assert!(resolve(&root, "..").is_err());
+        assert!(resolve(&root, "src/../../..").is_err());
+        assert!(resolve(&root, "/etc").is_err());
+    }
+
+    fn get(addr: &str, target: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn search_over_http() {
+        let root = std::env::temp_dir().join(format!("minigrep-serve-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/poem.txt"), "I'm nobody!\nWho are you?\n").unwrap();
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/data"), b"\xff\xfe").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let config = ServeConfig {
+            root: root.canonicalize().unwrap(),
+            port: 0,
+            max_requests: 2,
+        };
+        thread::spawn(move || serve_on(listener, config));
+
+        let response = get(&addr, "/search?q=WHO&ignore_case=true&path=docs");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Transfer-Encoding: chunked"));
+        assert!(response.contains(r#"{"path":"docs/poem.txt","line":2,"text":"Who are you?"}"#));
+        assert!(response.contains(r#""files":1,"errors":[]"#));
+        assert!(response.ends_with("0\r\n\r\n"));
+
+        // errors don't give away where the root is
+        let response = get(&addr, "/search?q=x&path=bin");
+        assert!(
+            response.contains(r#""errors":["bin/data: "#),
+            "{}",
+            response
+        );
+        assert!(!response.contains(&*root.canonicalize().unwrap().to_string_lossy()));
+
+        assert!(get(&addr, "/search?q=x&path=..").starts_with("HTTP/1.1 403"));
+        assert!(get(&addr, "/search?path=docs").starts_with("HTTP/1.1 400"));
+        assert!(get(&addr, "/other").starts_with("HTTP/1.1 404"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn limit_concurrent_requests() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let first = Slot::take(&active, 1);
+        assert!(first.is_some());
+        assert!(Slot::take(&active, 1).is_none());
+        drop(first);
+        assert!(Slot::take(&active, 1).is_some());
+    }
+}
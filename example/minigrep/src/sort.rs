@@ -0,0 +1,164 @@
+//! `--sort` and `--sortr`: search files in a fixed order so the output is the same every run
+
+use std::fs::Metadata;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::walk::WalkError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Path,
+    Modified,
+    Accessed,
+    Created,
+}
+
+impl SortBy {
+    pub fn parse(text: &str) -> Result<SortBy, String> {
+        match text {
+            "path" => Ok(SortBy::Path),
+            "modified" => Ok(SortBy::Modified),
+            "accessed" => Ok(SortBy::Accessed),
+            "created" => Ok(SortBy::Created),
+            _ => Err(format!(
+                "can't sort by '{}', use path, modified, accessed or created",
+                text
+            )),
+        }
+    }
+
+    // `None` when the platform or file system doesn't keep this time
+    fn time(self, meta: &Metadata) -> Option<SystemTime> {
+        let time: io::Result<SystemTime> = match self {
+            SortBy::Path => return None,
+            SortBy::Modified => meta.modified(),
+            SortBy::Accessed => meta.accessed(),
+            SortBy::Created => meta.created(),
+        };
+        time.ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort {
+    pub by: SortBy,
+    // `--sortr`, largest first
+    pub reverse: bool,
+}
+
+/// order the walked files, directories that couldn't be read go by their own path.
+///
+/// files without the requested time come first, ties are broken by path
+pub fn sort_files(
+    files: Vec<Result<PathBuf, WalkError>>,
+    sort: Sort,
+) -> Vec<Result<PathBuf, WalkError>> {
+    // read every time once up front rather than on each comparison
+    let mut keyed: Vec<(Option<SystemTime>, PathBuf, Result<PathBuf, WalkError>)> = files
+        .into_iter()
+        .map(|entry| {
+            let path = match &entry {
+                Ok(path) => path.clone(),
+                Err(e) => e.path.clone(),
+            };
+            let time = path.metadata().ok().and_then(|meta| sort.by.time(&meta));
+            (time, path, entry)
+        })
+        .collect();
+
+    keyed.sort_by(|a, b| {
+        let order = a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1));
+        if sort.reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    keyed.into_iter().map(|(_, _, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    fn names(files: &[Result<PathBuf, WalkError>]) -> Vec<String> {
+        files
+            .iter()
+            .map(|entry| {
+                let path = entry.as_ref().unwrap();
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn by_path() {
+        let mut files: Vec<Result<PathBuf, WalkError>> = ["b/x", "a/z", "c", "a/y"]
+            .iter()
+            .map(|p| Ok(PathBuf::from(p)))
+            .collect();
+
+        files = sort_files(
+            files,
+            Sort {
+                by: SortBy::Path,
+                reverse: false,
+            },
+        );
+        assert_eq!(vec!["y", "z", "x", "c"], names(&files));
+
+        files = sort_files(
+            files,
+            Sort {
+                by: SortBy::Path,
+                reverse: true,
+            },
+        );
+        assert_eq!(vec!["c", "x", "z", "y"], names(&files));
+    }
+
+    #[test]
+    fn by_modified_time() {
+        let dir = std::env::temp_dir().join(format!("minigrep-sort-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        // the names run the other way from the times
+        for (name, age) in [("a", 30), ("b", 20), ("c", 10)] {
+            let path = dir.join(name);
+            let file = fs::File::create(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(age)).unwrap();
+            files.push(Ok(path));
+        }
+        files.reverse();
+
+        files = sort_files(
+            files,
+            Sort {
+                by: SortBy::Modified,
+                reverse: false,
+            },
+        );
+        assert_eq!(vec!["a", "b", "c"], names(&files));
+        files = sort_files(
+            files,
+            Sort {
+                by: SortBy::Modified,
+                reverse: true,
+            },
+        );
+        assert_eq!(vec!["c", "b", "a"], names(&files));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_sort_by() {
+        assert_eq!(Ok(SortBy::Accessed), SortBy::parse("accessed"));
+        assert!(SortBy::parse("size").is_err());
+    }
+}
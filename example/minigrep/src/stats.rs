@@ -0,0 +1,109 @@
+//! numbers collected while searching, printed by `--stats` and returned from `run`
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub files_scanned: usize,
+    // bytes read from disk, before any decompression
+    pub bytes_read: u64,
+    pub files_matched: usize,
+    pub matched_lines: usize,
+    // a line can hold more than one match
+    pub matches: usize,
+    // files and directories that couldn't be read
+    pub errors: usize,
+    // `--json-path` lines that couldn't be parsed, which aren't errors
+    pub invalid_json: usize,
+    // the search ran out of time before every file was searched
+    pub timed_out: bool,
+    // `--budget-bytes` or `--budget-matches` was reached and the search stopped
+    pub over_budget: bool,
+    // with `minigrep diff`, the lines added, removed or moved, which decide the
+    // exit status instead of the matching lines
+    pub changes: Option<usize>,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// bytes read per second, 0 when no time was measured
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_read as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// grep's exit status: 2 if anything went wrong or the budget ran out,
+    /// otherwise 0 on a match (or a change, for diff) and 1 without
+    pub fn exit_code(&self) -> i32 {
+        if self.errors > 0 || self.over_budget {
+            2
+        } else if self.changes.unwrap_or(self.matched_lines) > 0 {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "files scanned:      {}", self.files_scanned)?;
+        writeln!(f, "bytes read:         {}", self.bytes_read)?;
+        writeln!(f, "files with matches: {}", self.files_matched)?;
+        writeln!(f, "matching lines:     {}", self.matched_lines)?;
+        writeln!(f, "matches:            {}", self.matches)?;
+        if let Some(changes) = self.changes {
+            writeln!(f, "changes:            {}", changes)?;
+        }
+        writeln!(f, "errors:             {}", self.errors)?;
+        if self.invalid_json > 0 {
+            writeln!(f, "invalid JSON lines: {}", self.invalid_json)?;
+        }
+        writeln!(f, "time:               {:.3}s", self.elapsed.as_secs_f64())?;
+        write!(
+            f,
+            "throughput:         {:.2} MiB/s",
+            self.throughput() / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throughput() {
+        let stats = Stats {
+            bytes_read: 3 * 1024 * 1024,
+            elapsed: Duration::from_millis(500),
+            ..Stats::default()
+        };
+
+        assert_eq!(6.0 * 1024.0 * 1024.0, stats.throughput());
+        assert!(stats
+            .to_string()
+            .ends_with("throughput:         6.00 MiB/s"));
+        assert_eq!(0.0, Stats::default().throughput());
+    }
+
+    #[test]
+    fn exit_code() {
+        let mut stats = Stats::default();
+        assert_eq!(1, stats.exit_code());
+
+        stats.matched_lines = 3;
+        assert_eq!(0, stats.exit_code());
+        // diff reports changes, matching lines alone aren't one
+        stats.changes = Some(0);
+        assert_eq!(1, stats.exit_code());
+
+        stats.errors = 1;
+        assert_eq!(2, stats.exit_code());
+    }
+}
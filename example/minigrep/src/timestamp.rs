@@ -0,0 +1,494 @@
+//! `--since` and `--until`: only keep lines from a window of time
+//!
+//! each line is scanned for the first timestamp in one of these forms:
+//!
+//! - RFC 3339, `2024-04-04T10:00:00.123+02:00`, also with a space for the `T`
+//! - syslog, `Apr  4 10:00:00`, which has no year
+//! - common log format, `[04/Apr/2024:10:00:00 +0200]`
+//! - the `--time-format` given, with `%Y %m %d %H %M %S %b %z %s` and `%%`
+//!
+//! times without a zone are taken as UTC, and a syslog time as being in the
+//! year of `--since` (or `--until`, or this year). a line without a timestamp,
+//! like the rest of a stack trace, goes with the line before it.
+//!
+//! with `--sorted` the log is taken to be in time order: a binary search finds
+//! the first line of the window and the search stops at the first line after it
+
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cancel::{self, Cancel};
+use crate::regex::Regex;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// how timestamps are recognised
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formats {
+    // `--time-format`, tried before the built in forms
+    pub custom: Option<String>,
+    // for formats without a year
+    pub year: i64,
+}
+
+/// the lines to keep, as seconds since the Unix epoch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub formats: Formats,
+    pub sorted: bool,
+}
+
+impl Window {
+    /// build the window from the option values, each a timestamp in one of the
+    /// forms above, a date, or a duration such as `2h` meaning that long ago
+    pub fn new(
+        since: Option<&str>,
+        until: Option<&str>,
+        custom: Option<String>,
+        sorted: bool,
+    ) -> Result<Window, String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let mut formats = Formats {
+            custom,
+            year: year_of(now),
+        };
+        // a syslog time in the bounds themselves is in this year
+        let bound = |text: Option<&str>| -> Result<Option<i64>, String> {
+            let time = match text {
+                Some(text) => parse_bound(text, &formats, now)?,
+                None => return Ok(None),
+            };
+            Ok(Some(time))
+        };
+        let since = bound(since)?;
+        let until = bound(until)?;
+        if let Some(time) = since.or(until) {
+            formats.year = year_of(time);
+        }
+        Ok(Window {
+            since,
+            until,
+            formats,
+            sorted,
+        })
+    }
+
+    fn contains(&self, time: i64) -> bool {
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+fn parse_bound(text: &str, formats: &Formats, now: i64) -> Result<i64, String> {
+    let text = text.trim();
+    if let Some((time, len)) = parse_at(text.as_bytes(), 0, formats) {
+        if len == text.len() {
+            return Ok(time);
+        }
+    }
+    // a date alone is its midnight
+    let mut cursor = Cursor::new(text.as_bytes(), 0);
+    if let Some(days) = cursor.date() {
+        if cursor.pos == text.len() {
+            return Ok(days * 86400);
+        }
+    }
+    let ago =
+        cancel::parse_duration(text).map_err(|_| format!("can't read '{}' as a time", text))?;
+    i64::try_from(ago.as_secs())
+        .ok()
+        .and_then(|ago| now.checked_sub(ago))
+        .ok_or_else(|| format!("'{}' is too long ago", text))
+}
+
+/// the first timestamp in `line`
+pub fn find(line: &str, formats: &Formats) -> Option<i64> {
+    let bytes = line.as_bytes();
+    (0..bytes.len())
+        // a timestamp starts at a word, not in the middle of a number or name
+        .filter(|&i| i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+        .find_map(|i| parse_at(bytes, i, formats).map(|(time, _)| time))
+}
+
+// a timestamp starting at `start`, and how many bytes it took
+fn parse_at(bytes: &[u8], start: usize, formats: &Formats) -> Option<(i64, usize)> {
+    // a user's format goes first, it may look like one of the others
+    (0..4).find_map(|form| {
+        let mut cursor = Cursor::new(bytes, start);
+        let time = match form {
+            0 => cursor.custom(formats),
+            1 => cursor.rfc3339(),
+            2 => cursor.common_log(),
+            _ => cursor.syslog(formats.year),
+        }?;
+        Some((time, cursor.pos - start))
+    })
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], pos: usize) -> Cursor<'a> {
+        Cursor { bytes, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn byte(&mut self, b: u8) -> Option<()> {
+        if self.peek()? == b {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    // exactly `n` digits
+    fn number(&mut self, n: usize) -> Option<i64> {
+        let digits = self.bytes.get(self.pos..self.pos + n)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.pos += n;
+        Some(digits.iter().fold(0, |n, d| n * 10 + i64::from(d - b'0')))
+    }
+
+    // 1-based
+    fn month_name(&mut self) -> Option<i64> {
+        let name = self.bytes.get(self.pos..self.pos + 3)?;
+        let month = MONTHS.iter().position(|m| m.as_bytes() == name)?;
+        self.pos += 3;
+        Some(month as i64 + 1)
+    }
+
+    // `YYYY-MM-DD` as days since the epoch
+    fn date(&mut self) -> Option<i64> {
+        let year = self.number(4)?;
+        self.byte(b'-')?;
+        let month = self.number(2)?;
+        self.byte(b'-')?;
+        let day = self.number(2)?;
+        days(year, month, day)
+    }
+
+    // `HH:MM:SS` as seconds into the day
+    fn time(&mut self) -> Option<i64> {
+        let hour = self.number(2)?;
+        self.byte(b':')?;
+        let minute = self.number(2)?;
+        self.byte(b':')?;
+        let second = self.number(2)?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        Some(hour * 3600 + minute * 60 + second)
+    }
+
+    // `Z`, `+02:00` or `+0200` as seconds east of UTC
+    fn zone(&mut self) -> Option<i64> {
+        match self.peek()? {
+            b'Z' | b'z' => {
+                self.pos += 1;
+                Some(0)
+            }
+            sign @ (b'+' | b'-') => {
+                self.pos += 1;
+                let hours = self.number(2)?;
+                let _ = self.byte(b':');
+                let minutes = self.number(2)?;
+                let offset = hours * 3600 + minutes * 60;
+                Some(if sign == b'-' { -offset } else { offset })
+            }
+            _ => None,
+        }
+    }
+
+    fn rfc3339(&mut self) -> Option<i64> {
+        let days = self.date()?;
+        match self.peek()? {
+            b'T' | b't' | b' ' => self.pos += 1,
+            _ => return None,
+        }
+        let time = self.time()?;
+        // fractions of a second don't move a line in or out of the window
+        if self.byte(b'.').is_some() {
+            while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let zone = self.zone().unwrap_or(0);
+        Some(days * 86400 + time - zone)
+    }
+
+    fn common_log(&mut self) -> Option<i64> {
+        self.byte(b'[')?;
+        let day = self.number(2)?;
+        self.byte(b'/')?;
+        let month = self.month_name()?;
+        self.byte(b'/')?;
+        let year = self.number(4)?;
+        self.byte(b':')?;
+        let time = self.time()?;
+        self.byte(b' ')?;
+        let zone = self.zone()?;
+        self.byte(b']')?;
+        Some(days(year, month, day)? * 86400 + time - zone)
+    }
+
+    fn syslog(&mut self, year: i64) -> Option<i64> {
+        let month = self.month_name()?;
+        self.byte(b' ')?;
+        // the day is padded with a space
+        let day = match self.byte(b' ') {
+            Some(()) => self.number(1)?,
+            None => self.number(2)?,
+        };
+        self.byte(b' ')?;
+        let time = self.time()?;
+        Some(days(year, month, day)? * 86400 + time)
+    }
+
+    fn custom(&mut self, formats: &Formats) -> Option<i64> {
+        let format = formats.custom.as_ref()?.as_bytes();
+        let (mut year, mut month, mut day) = (formats.year, 1, 1);
+        let (mut hour, mut minute, mut second, mut zone) = (0, 0, 0, 0);
+        let mut epoch = None;
+
+        let mut i = 0;
+        while i < format.len() {
+            if format[i] != b'%' || i + 1 == format.len() {
+                self.byte(format[i])?;
+                i += 1;
+                continue;
+            }
+            match format[i + 1] {
+                b'Y' => year = self.number(4)?,
+                b'm' => month = self.number(2)?,
+                b'd' => day = self.number(2)?,
+                b'H' => hour = self.number(2)?,
+                b'M' => minute = self.number(2)?,
+                b'S' => second = self.number(2)?,
+                b'b' => month = self.month_name()?,
+                b'z' => zone = self.zone()?,
+                b's' => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                        self.pos += 1;
+                    }
+                    epoch = Some(
+                        std::str::from_utf8(&self.bytes[start..self.pos])
+                            .ok()?
+                            .parse()
+                            .ok()?,
+                    );
+                }
+                other => self.byte(other)?,
+            }
+            i += 2;
+        }
+
+        if epoch.is_some() {
+            return epoch;
+        }
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        Some(days(year, month, day)? * 86400 + hour * 3600 + minute * 60 + second - zone)
+    }
+}
+
+// days from 1970-01-01 to a date of the proleptic Gregorian calendar
+fn days(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // count years from March, so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+// the year a time falls in
+fn year_of(time: i64) -> i64 {
+    let mut year = 1970 + time.div_euclid(86400 * 366);
+    while days(year + 1, 1, 1).is_some_and(|d| d * 86400 <= time) {
+        year += 1;
+    }
+    year
+}
+
+/// the lines inside the window that `pattern` matches
+pub fn search(
+    window: &Window,
+    pattern: &Regex,
+    text: &str,
+    max: usize,
+    cancel: &Cancel,
+) -> Vec<(usize, String)> {
+    let (start, mut number) = if window.sorted && window.since.is_some() {
+        let start = window_start(window, text);
+        (start, text[..start].matches('\n').count() + 1)
+    } else {
+        (0, 1)
+    };
+
+    let mut results = Vec::new();
+    // whether the last timestamp seen was inside the window
+    let mut inside = false;
+    for line in text[start..].lines() {
+        if results.len() == max || cancel.is_cancelled() {
+            break;
+        }
+        if let Some(time) = find(line, &window.formats) {
+            if window.sorted && window.until.is_some_and(|until| time > until) {
+                break;
+            }
+            inside = window.contains(time);
+        }
+        if inside && pattern.is_match(line) {
+            results.push((number, line.to_string()));
+        }
+        number += 1;
+    }
+    results
+}
+
+// the start of the first line at or after `since` in a sorted log
+fn window_start(window: &Window, text: &str) -> usize {
+    let since = match window.since {
+        Some(since) => since,
+        None => return 0,
+    };
+    let bytes = text.as_bytes();
+    // the first line that starts at or after `pos`
+    let line_at = |pos: usize| match pos {
+        0 => 0,
+        _ => bytes[pos - 1..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(text.len(), |i| pos + i),
+    };
+    // whether the first timestamp from the line at `pos` on is already in the window,
+    // false up to some position and true from there on in a sorted log
+    let reached = |pos: usize| {
+        text[line_at(pos)..]
+            .lines()
+            .find_map(|line| find(line, &window.formats))
+            .is_none_or(|time| time >= since)
+    };
+
+    let (mut lo, mut hi) = (0, text.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if reached(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    line_at(lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(custom: Option<&str>) -> Formats {
+        Formats {
+            custom: custom.map(String::from),
+            year: 2024,
+        }
+    }
+
+    // 2024-04-04T10:00:00Z
+    const TEN: i64 = 1_712_224_800;
+
+    #[test]
+    fn finds_timestamps() {
+        let f = formats(None);
+        assert_eq!(Some(TEN), find("2024-04-04T10:00:00Z GET /", &f));
+        assert_eq!(Some(TEN), find("at 2024-04-04 12:00:00.5+02:00", &f));
+        assert_eq!(
+            Some(TEN),
+            find("127.0.0.1 - - [04/Apr/2024:11:00:00 +0100] \"GET /\"", &f)
+        );
+        assert_eq!(Some(TEN), find("Apr  4 10:00:00 host sshd[1]: ok", &f));
+        assert_eq!(None, find("no time here, just 10:00", &f));
+
+        let f = formats(Some("%d.%m.%Y %H:%M"));
+        assert_eq!(Some(TEN), find("[04.04.2024 10:00] up", &f));
+        let f = formats(Some("ts=%s"));
+        assert_eq!(Some(TEN), find("ts=1712224800 up", &f));
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(Some(0), days(1970, 1, 1));
+        assert_eq!(Some(19_817), days(2024, 4, 4));
+        assert_eq!(Some(-1), days(1969, 12, 31));
+        assert_eq!(2024, year_of(TEN));
+        assert_eq!(2023, year_of(days(2024, 1, 1).unwrap() * 86400 - 1));
+    }
+
+    #[test]
+    fn bounds() {
+        let window = Window::new(
+            Some("2024-04-04"),
+            Some("2024-04-04T10:00:00Z"),
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(Some(TEN - 10 * 3600), window.since);
+        assert_eq!(Some(TEN), window.until);
+        assert_eq!(2024, window.formats.year);
+        assert!(Window::new(Some("yesterday"), None, None, false).is_err());
+        assert!(Window::new(Some("99999999999999999999h"), None, None, false).is_err());
+    }
+
+    #[test]
+    fn window_lines() {
+        let log: String = (0..100)
+            .map(|minute| {
+                let extra = if minute % 10 == 0 { "\n  at trace" } else { "" };
+                format!(
+                    "2024-04-04T10:{:02}:00Z event {}{}\n",
+                    minute / 2,
+                    minute,
+                    extra
+                )
+            })
+            .collect();
+        let pattern = Regex::literal("", false);
+        let run = |sorted| {
+            let window = Window::new(
+                Some("2024-04-04T10:20:00Z"),
+                Some("2024-04-04T10:21:00Z"),
+                None,
+                sorted,
+            )
+            .unwrap();
+            search(&window, &pattern, &log, usize::MAX, &Cancel::new())
+        };
+
+        let scanned = run(false);
+        // four events and the trace after the first
+        assert_eq!(5, scanned.len());
+        assert!(scanned[0].1.ends_with("event 40"));
+        assert_eq!("  at trace", scanned[1].1);
+        assert_eq!(run(true), scanned);
+    }
+}
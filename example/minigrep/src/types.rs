@@ -0,0 +1,87 @@
+//! named file types for `-t` / `-T`, each one a list of globs
+
+use std::collections::HashMap;
+
+// name and globs of every type known out of the box
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("css", &["*.css", "*.scss"]),
+    ("csv", &["*.csv", "*.tsv"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.jsx"]),
+    ("json", &["*.json", "*.jsonl"]),
+    ("log", &["*.log", "*.log.[0-9]*", "*.log.gz"]),
+    ("markdown", &["*.md", "*.markdown", "*.mdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+pub struct Types {
+    defs: HashMap<String, Vec<String>>,
+}
+
+impl Types {
+    /// the built-in table
+    pub fn new() -> Types {
+        let mut defs = HashMap::new();
+        for (name, globs) in DEFAULT_TYPES {
+            let globs = globs.iter().map(|glob| glob.to_string()).collect();
+            defs.insert(name.to_string(), globs);
+        }
+        Types { defs }
+    }
+
+    /// add a definition written as `name:glob`, e.g. `proto:*.proto`.
+    /// adding to a name that already exists extends it
+    pub fn add(&mut self, def: &str) -> Result<(), String> {
+        let (name, glob) = match def.find(':') {
+            Some(i) if i > 0 && i + 1 < def.len() => (&def[..i], &def[i + 1..]),
+            _ => return Err(format!("type definition '{}' is not name:glob", def)),
+        };
+
+        let globs = self.defs.entry(name.to_string()).or_default();
+        globs.push(glob.to_string());
+        Ok(())
+    }
+
+    pub fn globs(&self, name: &str) -> Result<&[String], String> {
+        self.defs
+            .get(name)
+            .map(|globs| globs.as_slice())
+            .ok_or_else(|| format!("unknown file type '{}'", name))
+    }
+}
+
+impl Default for Types {
+    fn default() -> Types {
+        Types::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_extend() {
+        let mut types = Types::new();
+        types.add("proto:*.proto").unwrap();
+        types.add("rust:*.rs.in").unwrap();
+
+        assert_eq!(&["*.proto"], types.globs("proto").unwrap());
+        assert_eq!(&["*.rs", "*.rs.in"], types.globs("rust").unwrap());
+        assert!(types.add("no-glob").is_err());
+        assert!(types.globs("cobol").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! turn the paths given on the command line into the list of files to search
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::{Glob, GlobError};
+
+/// a directory that couldn't be listed
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub err: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.err)
+    }
+}
+
+impl Error for WalkError {}
+
+/// decides which files found inside a directory get searched.
+///
+/// with any include globs a file has to match one of them, and a file matching
+/// an exclude glob is always skipped. files named on the command line are
+/// searched no matter what, like in grep
+#[derive(Debug, Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl Filter {
+    /// `globs` starting with `!` exclude, the others include
+    pub fn new(globs: &[String]) -> Result<Filter, GlobError> {
+        let mut filter = Filter::default();
+        for glob in globs {
+            match glob.strip_prefix('!') {
+                Some(glob) => filter.exclude(glob)?,
+                None => filter.include(glob)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn include(&mut self, glob: &str) -> Result<(), GlobError> {
+        self.include.push(Glob::new(glob)?);
+        Ok(())
+    }
+
+    pub fn exclude(&mut self, glob: &str) -> Result<(), GlobError> {
+        self.exclude.push(Glob::new(glob)?);
+        Ok(())
+    }
+
+    /// `path` is relative to the directory given on the command line
+    pub fn allows_file(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(path)))
+            && !self.exclude.iter().any(|glob| glob.is_match(path))
+    }
+
+    // skip a whole directory when it, or everything under it, is excluded
+    fn allows_dir(&self, path: &Path) -> bool {
+        let inside = path.join("");
+        !self
+            .exclude
+            .iter()
+            .any(|glob| glob.is_match(path) || glob.is_match(&inside))
+    }
+}
+
+/// every file named in `paths`, descending into directories.
+///
+/// a directory that can't be read shows up as an error in its place,
+/// so the caller can report it and carry on with the rest
+pub fn files(paths: &[String], filter: &Filter) -> Vec<Result<PathBuf, WalkError>> {
+    let mut found = Vec::new();
+    for path in paths {
+        let root = Path::new(path);
+        if root.is_dir() {
+            visit(root, root, filter, &mut found);
+        } else {
+            found.push(Ok(root.to_path_buf()));
+        }
+    }
+    found
+}
+
+fn visit(root: &Path, path: &Path, filter: &Filter, found: &mut Vec<Result<PathBuf, WalkError>>) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            found.push(Err(WalkError {
+                path: path.to_path_buf(),
+                err,
+            }));
+            return;
+        }
+    };
+
+    for entry in entries {
+        let (entry, file_type) = match entry.and_then(|e| e.file_type().map(|t| (e, t))) {
+            Ok(entry) => entry,
+            Err(err) => {
+                found.push(Err(WalkError {
+                    path: path.to_path_buf(),
+                    err,
+                }));
+                continue;
+            }
+        };
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        // symlinked directories are not followed, so a link cycle can't loop forever
+        if file_type.is_dir() {
+            if filter.allows_dir(relative) {
+                visit(root, &path, filter, found);
+            }
+        } else if (file_type.is_file() || path.is_file()) && filter.allows_file(relative) {
+            found.push(Ok(path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_globs() {
+        let globs = ["*.rs".to_string(), "!tests/**".to_string()];
+        let filter = Filter::new(&globs).unwrap();
+
+        assert!(filter.allows_file(Path::new("src/lib.rs")));
+        assert!(!filter.allows_file(Path::new("tests/cli.rs")));
+        assert!(!filter.allows_file(Path::new("Cargo.toml")));
+        assert!(!filter.allows_dir(Path::new("tests")));
+        assert!(filter.allows_dir(Path::new("src")));
+    }
+
+    #[test]
+    fn walk_applies_filter() {
+        let filter = Filter::new(&["!*.rs".to_string()]).unwrap();
+        let found: Vec<PathBuf> = files(&["src".to_string(), "src/lib.rs".to_string()], &filter)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        // the explicitly named file is kept
+        assert_eq!(vec![PathBuf::from("src/lib.rs")], found);
+    }
+}
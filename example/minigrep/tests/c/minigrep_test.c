@@ -0,0 +1,70 @@
+/* exercises every function in include/minigrep.h, run by tests/c_abi.rs */
+#include <stdio.h>
+#include <string.h>
+
+#include "minigrep.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__, #cond); \
+            failures++;                                               \
+        }                                                             \
+    } while (0)
+
+static const char POEM[] =
+    "I'm nobody! Who are you?\n"
+    "Are you nobody, too?\n"
+    "Then there's a pair of us - don't tell!\n";
+
+int main(void) {
+    MinigrepMatch found;
+
+    /* a plain query, ignoring case */
+    MinigrepQuery *query = minigrep_query_new("NOBODY", MINIGREP_IGNORE_CASE);
+    CHECK(query != NULL);
+    MinigrepMatches *matches = minigrep_search(query, (const uint8_t *)POEM, strlen(POEM));
+    CHECK(matches != NULL);
+    CHECK(minigrep_matches_count(matches) == 2);
+
+    CHECK(minigrep_matches_next(matches, &found));
+    CHECK(found.line == 1);
+    CHECK(strncmp(POEM + found.start, "nobody", found.end - found.start) == 0);
+    CHECK(minigrep_matches_next(matches, &found));
+    CHECK(found.line == 2);
+    CHECK(strncmp(POEM + found.line_start, "Are you nobody, too?", found.line_end - found.line_start) == 0);
+    CHECK(!minigrep_matches_next(matches, &found));
+    minigrep_matches_free(matches);
+    minigrep_query_free(query);
+
+    /* a regular expression with more than one match on a line */
+    query = minigrep_query_new("\\b[a-z]{3}\\b", MINIGREP_REGEX);
+    CHECK(query != NULL);
+    matches = minigrep_search(query, (const uint8_t *)POEM, strlen(POEM));
+    CHECK(minigrep_matches_count(matches) == 5);
+    /* "are" and "you" */
+    CHECK(minigrep_matches_next(matches, &found) && found.line == 1);
+    CHECK(minigrep_matches_next(matches, &found) && found.line == 1);
+    minigrep_matches_free(matches);
+
+    /* text that isn't UTF-8 */
+    const uint8_t bad[] = {'a', 0xff, '\n'};
+    CHECK(minigrep_search(query, bad, sizeof bad) == NULL);
+    CHECK(strlen(minigrep_last_error()) > 0);
+    minigrep_query_free(query);
+
+    /* an invalid pattern */
+    CHECK(minigrep_query_new("(unclosed", MINIGREP_REGEX) == NULL);
+    CHECK(strlen(minigrep_last_error()) > 0);
+
+    /* NULL is always safe to free */
+    minigrep_query_free(NULL);
+    minigrep_matches_free(NULL);
+
+    if (failures == 0) {
+        printf("ok\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
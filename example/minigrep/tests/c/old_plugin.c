@@ -0,0 +1,23 @@
+/* a plugin built for an ABI version minigrep doesn't know. its table has a
+ * different layout, so reading it would crash; minigrep has to refuse it */
+
+#include <stdint.h>
+
+struct OldMatcher {
+    int (*matches)(const char *line);
+};
+
+static int never(const char *line) {
+    (void)line;
+    return 0;
+}
+
+static const struct OldMatcher old = {never};
+
+uint32_t minigrep_plugin_abi_version(void) {
+    return 999;
+}
+
+const struct OldMatcher *minigrep_plugin_matchers(void) {
+    return &old;
+}
@@ -0,0 +1,63 @@
+/* an example plugin: `--matcher status_at_least 500` finds the lines of an
+ * access log whose status code is 500 or more, `--matcher word` whole words */
+
+#include <ctype.h>
+#include <string.h>
+
+#include "minigrep_plugin.h"
+
+static long number(const char *text, size_t len) {
+    long n = 0;
+    for (size_t i = 0; i < len && isdigit((unsigned char)text[i]); i++) {
+        n = n * 10 + (text[i] - '0');
+    }
+    return n;
+}
+
+/* the status is the first three digit number standing on its own */
+static int status_at_least(const char *query, size_t query_len,
+                           const char *line, size_t line_len) {
+    long least = number(query, query_len);
+    for (size_t i = 0; i + 3 <= line_len; i++) {
+        int before = i == 0 || line[i - 1] == ' ';
+        int after = i + 3 == line_len || line[i + 3] == ' ';
+        if (before && after && isdigit((unsigned char)line[i]) &&
+            isdigit((unsigned char)line[i + 1]) &&
+            isdigit((unsigned char)line[i + 2])) {
+            return number(line + i, 3) >= least;
+        }
+    }
+    return 0;
+}
+
+static int is_word(char c) {
+    return isalnum((unsigned char)c) || c == '_';
+}
+
+static int word(const char *query, size_t query_len, const char *line,
+                size_t line_len) {
+    for (size_t i = 0; query_len > 0 && i + query_len <= line_len; i++) {
+        if (memcmp(line + i, query, query_len) == 0 &&
+            (i == 0 || !is_word(line[i - 1])) &&
+            (i + query_len == line_len || !is_word(line[i + query_len]))) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+static const MinigrepMatcherDef matchers[] = {
+    {"status_at_least", "access log lines with a status of at least the query",
+     status_at_least},
+    {"word", "the query as a whole word", word},
+};
+
+static const MinigrepPluginDef plugin = {2, matchers};
+
+uint32_t minigrep_plugin_abi_version(void) {
+    return MINIGREP_PLUGIN_ABI_VERSION;
+}
+
+const MinigrepPluginDef *minigrep_plugin_matchers(void) {
+    return &plugin;
+}
@@ -0,0 +1,58 @@
+// builds tests/c/minigrep_test.c against the cdylib and runs it.
+// skipped, with a note, where there's no C compiler
+#![cfg(unix)]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn c_program_uses_every_function() {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the test runs from target/<profile>/deps, the library is one up
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().parent().unwrap();
+    // `cargo test` only builds the Rust library, the C one has to be asked for
+    let mut build = Command::new(env!("CARGO"));
+    build.args(["build", "--lib", "--quiet", "--manifest-path"]);
+    build.arg(manifest.join("Cargo.toml"));
+    if lib_dir.ends_with("release") {
+        build.arg("--release");
+    }
+    assert!(
+        build.status().unwrap().success(),
+        "building the cdylib failed"
+    );
+
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("minigrep_test");
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+
+    let compiled = Command::new(&cc)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(manifest.join("tests/c/minigrep_test.c"))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg("-L")
+        .arg(lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lminigrep")
+        .arg("-o")
+        .arg(&program)
+        .status();
+    match compiled {
+        Ok(status) => assert!(status.success(), "compiling the C test program failed"),
+        Err(_) => {
+            eprintln!("no C compiler ('{}'), skipping", cc);
+            return;
+        }
+    }
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!("ok\n", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,50 @@
+// `--follow` on a log that grows, is truncated and is then rotated, while the
+// output is read from the still running process
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+fn append(path: &Path, text: &str) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+}
+
+#[test]
+fn follows_through_rotation() {
+    let dir = std::env::temp_dir().join(format!("minigrep-follow-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("app.log");
+    fs::write(&log, "error before the start\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .args(["--follow", "-n", "-m", "3", "--timeout", "30s", "error"])
+        .arg(&log)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    // let it open the file and go to the end
+    thread::sleep(Duration::from_millis(500));
+
+    append(&log, "info skipped\nerror one\n");
+    assert_eq!("3:error one", lines.next().unwrap().unwrap());
+
+    fs::write(&log, "").unwrap();
+    append(&log, "error two\n");
+    assert_eq!("1:error two", lines.next().unwrap().unwrap());
+
+    fs::rename(&log, dir.join("app.log.1")).unwrap();
+    fs::write(&log, "error three\n").unwrap();
+    assert_eq!("1:error three", lines.next().unwrap().unwrap());
+
+    // `-m 3` was reached
+    assert_eq!(Some(0), child.wait().unwrap().code());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,94 @@
+// `--git-history` against a repository made with git, once with loose objects
+// and again after `git gc` packed them. skipped, with a note, without git
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn git(repo: &Path, args: &[&str]) -> Option<String> {
+    git_at(repo, args, "2020-01-01T00:00:00")
+}
+
+// commits are ordered by their committer date
+fn git_at(repo: &Path, args: &[&str], date: &str) -> Option<String> {
+    let output = Command::new("git")
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .ok()?;
+    assert!(
+        output.status.success(),
+        "git {:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn history(repo: &Path, query: &str) -> (Option<i32>, String) {
+    history_with(repo, &[], query)
+}
+
+fn history_with(repo: &Path, options: &[&str], query: &str) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .arg("--git-history")
+        .args(options)
+        .arg(query)
+        .arg(repo)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn loose_and_packed() {
+    let repo = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("history");
+    let _ = fs::remove_dir_all(&repo);
+    fs::create_dir_all(repo.join("src")).unwrap();
+    if git(&repo, &["init", "-q"]).is_none() {
+        eprintln!("no git, skipping");
+        return;
+    }
+
+    fs::write(repo.join("a.txt"), "one\nold needle\n").unwrap();
+    // same content, so the same blob
+    fs::write(repo.join("src/b.txt"), "one\nold needle\n").unwrap();
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "-q", "-m", "first"]);
+    let first = git(&repo, &["rev-parse", "HEAD"]).unwrap();
+
+    fs::write(repo.join("a.txt"), "one\ntwo\n").unwrap();
+    fs::remove_file(repo.join("src/b.txt")).unwrap();
+    git_at(
+        &repo,
+        &["commit", "-q", "-a", "-m", "second"],
+        "2020-01-02T00:00:00",
+    );
+    let second = git(&repo, &["rev-parse", "HEAD"]).unwrap();
+
+    let expected = format!(
+        "{0}:a.txt:2:old needle\n{0}:src/b.txt:2:old needle\n",
+        first
+    );
+    assert_eq!((Some(0), expected.clone()), history(&repo, "needle"));
+    // newest commit first
+    let (_, ones) = history(&repo, "one");
+    assert!(ones.starts_with(&format!("{}:a.txt:1:one\n", second)));
+    assert_eq!((Some(1), String::new()), history(&repo, "haystack"));
+    // globs apply to the paths in each commit
+    assert_eq!(
+        (Some(0), format!("{}:src/b.txt:2:old needle\n", first)),
+        history_with(&repo, &["--glob", "src/**"], "needle")
+    );
+
+    git(&repo, &["gc", "-q", "--aggressive"]);
+    assert!(!repo.join(".git/objects").join(&first[..2]).exists());
+    assert_eq!((Some(0), expected), history(&repo, "needle"));
+    assert_eq!(ones, history(&repo, "one").1);
+}
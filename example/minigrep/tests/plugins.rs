@@ -0,0 +1,111 @@
+// builds the plugins in tests/c and loads them, both through the library and
+// with `--matcher` on the command line. skipped, with a note, where there's no
+// C compiler
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use minigrep::plugin;
+
+const LOG: &str = "\
+10.0.0.1 - - \"GET / HTTP/1.1\" 200 512
+10.0.0.2 - - \"GET /admin HTTP/1.1\" 503 0
+10.0.0.3 - - \"POST /login HTTP/1.1\" 500 13
+10.0.0.4 - - \"GET /missing HTTP/1.1\" 404 0
+";
+
+// compile tests/c/<name>.c into <dir>/<name>.so, false without a compiler
+fn build(name: &str, dir: &Path) -> bool {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let compiled = Command::new(&cc)
+        .args(["-Wall", "-Werror", "-shared", "-fPIC"])
+        .arg(manifest.join("tests/c").join(format!("{}.c", name)))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg("-o")
+        .arg(dir.join(format!("{}.so", name)))
+        .status();
+    match compiled {
+        Ok(status) => {
+            assert!(status.success(), "compiling {} failed", name);
+            true
+        }
+        Err(_) => {
+            eprintln!("no C compiler ('{}'), skipping", cc);
+            false
+        }
+    }
+}
+
+fn plugin_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn loads_matchers_and_rejects_other_versions() {
+    let dir = plugin_dir("plugins-load");
+    if !build("status_plugin", &dir) || !build("old_plugin", &dir) {
+        return;
+    }
+
+    // SAFETY: the plugins were just built from tests/c
+    let (matchers, errors) = unsafe { plugin::load_dir(&dir) };
+    let names: Vec<&str> = matchers.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(vec!["status_at_least", "word"], names);
+
+    assert_eq!(1, errors.len());
+    assert_eq!(dir.join("old_plugin.so"), errors[0].path);
+    assert!(errors[0].msg.contains("version 999"), "{}", errors[0]);
+
+    let status = &matchers[0];
+    let matched: Vec<&str> = LOG
+        .lines()
+        .filter(|line| status.is_match("500", line))
+        .collect();
+    assert_eq!(2, matched.len());
+    // a CRLF line reaches the plugin without its '\r'
+    assert!(status.is_match("500", "GET / 503\r"));
+    assert!(matchers[1].is_match("GET", "\"GET /"));
+    assert!(!matchers[1].is_match("GET", "GETS"));
+}
+
+#[test]
+fn matcher_on_the_command_line() {
+    let dir = plugin_dir("plugins-cli");
+    if !build("status_plugin", &dir) {
+        return;
+    }
+    let log = dir.join("access.log");
+    fs::write(&log, LOG).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .arg("--plugin-dir")
+        .arg(&dir)
+        .args(["--matcher", "status_at_least", "-n", "500"])
+        .arg(&log)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "2:10.0.0.2 - - \"GET /admin HTTP/1.1\" 503 0\n\
+         3:10.0.0.3 - - \"POST /login HTTP/1.1\" 500 13\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .arg("--plugin-dir")
+        .arg(&dir)
+        .args(["--matcher", "nope", "x"])
+        .arg(&log)
+        .output()
+        .unwrap();
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no plugin in"));
+}